@@ -0,0 +1,154 @@
+// Numerical calculus on functions that can fail, such as user defined functions.
+pub type Function<'a> = dyn FnMut(f64) -> Result<f64, String> + 'a;
+
+
+// Approximates the derivative of f at x using a central difference. The step size balances
+// truncation error against floating point cancellation, giving around 10 significant digits
+// for smooth functions.
+pub fn derivative(f: &mut Function, x: f64) -> Result<f64, String> {
+    let step = f64::EPSILON.cbrt() * x.abs().max(1.0);
+
+    // Make sure x + step is exactly representable, so the denominator matches the actual distance.
+    let step = (x + step) - x;
+
+    Ok((f(x + step)? - f(x - step)?) / (2.0 * step))
+}
+
+
+// Number of subintervals used by integral. This must be even.
+const SIMPSON_INTERVALS: usize = 1000;
+
+
+// Approximates the integral of f from a to b using Simpson's rule over a fixed number of
+// subintervals. This is exact for polynomials up to cubic, and for smooth functions the error
+// shrinks with the fourth power of the interval width, typically giving 10+ significant digits.
+// Functions with discontinuities or singularities inside the range will be much less accurate.
+pub fn integral(f: &mut Function, a: f64, b: f64) -> Result<f64, String> {
+    let width = (b - a) / SIMPSON_INTERVALS as f64;
+
+    let mut sum = f(a)? + f(b)?;
+
+    for i in 1..SIMPSON_INTERVALS {
+        let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+
+        sum += weight * f(a + width * i as f64)?;
+    }
+
+    Ok(sum * width / 3.0)
+}
+
+
+// Bisection halves the interval each step, so this is enough to reach full double precision.
+const MAX_BISECTIONS: usize = 200;
+
+// Bisection stops once the interval is this small relative to the root.
+const BISECTION_TOLERANCE: f64 = 1e-12;
+
+
+// Finds a root of f between a and b by repeated bisection. f(a) and f(b) must have opposite signs.
+// Returns the midpoint of the final interval once it has narrowed to within tolerance.
+pub fn root(f: &mut Function, a: f64, b: f64) -> Result<f64, String> {
+    let (mut low, mut high) = (a, b);
+    let (mut f_low, f_high) = (f(low)?, f(high)?);
+
+    if f_low == 0.0 {
+        return Ok(low);
+    }
+
+    if f_high == 0.0 {
+        return Ok(high);
+    }
+
+    if f_low.is_nan() || f_high.is_nan() || f_low.is_sign_negative() == f_high.is_sign_negative() {
+        return Err(String::from("solve() requires f(a) and f(b) to have opposite signs."));
+    }
+
+    for _ in 0..MAX_BISECTIONS {
+        let mid = low + (high - low) / 2.0;
+
+        if (high - low).abs() / 2.0 <= BISECTION_TOLERANCE * mid.abs().max(1.0) {
+            return Ok(mid);
+        }
+
+        let f_mid = f(mid)?;
+
+        if f_mid == 0.0 {
+            return Ok(mid);
+        }
+
+        if f_mid.is_nan() {
+            return Err(String::from("solve() encountered NaN."));
+        }
+
+        if f_mid.is_sign_negative() == f_low.is_sign_negative() {
+            low = mid;
+            f_low = f_mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Err(String::from("solve() did not converge."))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::approx_eq;
+
+
+    fn is_close(x: f64, y: f64) -> bool {
+        approx_eq(x, y, 1e-8)
+    }
+
+
+    #[test]
+    fn derivatives() {
+        assert!(is_close(derivative(&mut |x| Ok(x * x), 3.0).unwrap(), 6.0));
+        assert!(is_close(derivative(&mut |x| Ok(x.sin()), 0.0).unwrap(), 1.0));
+        assert!(is_close(derivative(&mut |x| Ok(x.exp()), 1.0).unwrap(), 1f64.exp()));
+        assert!(is_close(derivative(&mut |x| Ok(x.powi(3)), 1000.0).unwrap(), 3e6));
+        assert!(is_close(derivative(&mut |_| Ok(5.0), 2.0).unwrap(), 0.0));
+
+        assert_eq!(derivative(&mut |_| Err(String::from("Oops.")), 1.0).unwrap_err(), "Oops.");
+    }
+
+
+    #[test]
+    fn integrals() {
+        assert!(is_close(integral(&mut |x| Ok(x * x), 0.0, 3.0).unwrap(), 9.0));
+        assert!(is_close(integral(&mut |x| Ok(x * x * x), -1.0, 2.0).unwrap(), 3.75));
+        assert!(is_close(integral(&mut |x| Ok(x.sin()), 0.0, std::f64::consts::PI).unwrap(), 2.0));
+        assert!(is_close(integral(&mut |x| Ok(x.exp()), 0.0, 1.0).unwrap(), 1f64.exp() - 1.0));
+        assert!(is_close(integral(&mut |x| Ok(x * x), 3.0, 0.0).unwrap(), -9.0));
+        assert!(is_close(integral(&mut |x| Ok(x * x), 2.0, 2.0).unwrap(), 0.0));
+
+        let mut calls = 0;
+
+        integral(&mut |x| { calls += 1; Ok(x) }, 0.0, 1.0).unwrap();
+
+        assert_eq!(calls, SIMPSON_INTERVALS + 1);
+
+        assert_eq!(integral(&mut |_| Err(String::from("Oops.")), 0.0, 1.0).unwrap_err(), "Oops.");
+    }
+
+
+    #[test]
+    fn roots() {
+        assert!(is_close(root(&mut |x| Ok(x * x - 2.0), 0.0, 2.0).unwrap(), 2f64.sqrt()));
+        assert!(is_close(root(&mut |x| Ok(x * x - 2.0), 2.0, 0.0).unwrap(), 2f64.sqrt()));
+        assert!(is_close(root(&mut |x| Ok(x * x - 2.0), -2.0, 0.0).unwrap(), -(2f64.sqrt())));
+        assert!(is_close(root(&mut |x| Ok(x.cos()), 0.0, 3.0).unwrap(), std::f64::consts::FRAC_PI_2));
+        assert!(is_close(root(&mut |x| Ok(x - 1e6), 0.0, 1e7).unwrap(), 1e6));
+
+        assert_eq!(root(&mut |x| Ok(x - 1.0), 1.0, 5.0).unwrap(), 1.0);
+        assert_eq!(root(&mut |x| Ok(x - 5.0), 1.0, 5.0).unwrap(), 5.0);
+        assert_eq!(root(&mut |x| Ok(x), -1.0, 1.0).unwrap(), 0.0);
+
+        assert_eq!(root(&mut |x| Ok(x * x + 1.0), -1.0, 1.0).unwrap_err(), "solve() requires f(a) and f(b) to have opposite signs.");
+        assert_eq!(root(&mut |_| Ok(f64::NAN), -1.0, 1.0).unwrap_err(), "solve() requires f(a) and f(b) to have opposite signs.");
+        assert_eq!(root(&mut |x| Ok(if x == 0.0 { f64::NAN } else { x }), -1.0, 1.0).unwrap_err(), "solve() encountered NaN.");
+        assert_eq!(root(&mut |_| Err(String::from("Oops.")), 0.0, 1.0).unwrap_err(), "Oops.");
+    }
+}
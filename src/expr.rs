@@ -1,11 +1,15 @@
+use std::convert::TryFrom;
 use std::f64;
 use std::fmt;
 use std::iter::Peekable;
 use std::mem;
+use std::time::{Duration, Instant};
 
 use crate::Context;
+use crate::calculus;
 use crate::ops;
 use crate::ops::{OpFunction, OperatorRef};
+use crate::settings::{AngleUnit, IntegerConversion, Settings};
 use crate::tokens::{Token, Tokenizer};
 
 
@@ -13,11 +17,97 @@ use crate::tokens::{Token, Tokenizer};
 #[derive(Debug)]
 pub enum ExpressionNode {
     Constant { value: f64 },
+    Integer  { value: i64 },
     Operator { op: OperatorRef, args: Vec<ExpressionNode> },
     Function { name: String,    args: Vec<ExpressionNode> },
 }
 
 
+impl ExpressionNode {
+    // Applies a transform to every node of the tree, visiting children before their parents,
+    // so passes such as constant folding see already rewritten arguments.
+    pub fn rewrite(&mut self, mut f: impl FnMut(&mut ExpressionNode)) {
+        self.rewrite_with(&mut f);
+    }
+
+
+    // Renders the expression as fully parenthesized infix text, which the parser can read back in.
+    pub fn to_infix(&self) -> String {
+        fn format_call(name: &str, args: &[ExpressionNode]) -> String {
+            if args.is_empty() {
+                String::from(name)
+            } else {
+                format!("{}({})", name, args.iter().map(ExpressionNode::to_infix).collect::<Vec<String>>().join(", "))
+            }
+        }
+
+        match self {
+            // NaN and infinity have no literal syntax, so are written as divisions by zero.
+            ExpressionNode::Constant{ value } if value.is_nan() => String::from("(0/0)"),
+            ExpressionNode::Constant{ value } if value.is_infinite() => format!("({}1/0)", if *value < 0.0 { "-" } else { "" }),
+            ExpressionNode::Constant{ value } if *value < 0.0 => format!("({})", value),
+            ExpressionNode::Constant{ value } => value.to_string(),
+            ExpressionNode::Integer{ value } => value.to_string(),
+
+            ExpressionNode::Function{ name, args } => format_call(name, args),
+
+            ExpressionNode::Operator{ op, args } => match args.as_slice() {
+                _ if matches!(op.precedence, ops::Precedence::None) => format_call(op.name, args),
+                [ x ] if ops::is_percent(op) => format!("(({})%)", x.to_infix()),
+                [ x ]          => format!("{}({})", op.name, x.to_infix()),
+                [ x, y ]       => format!("({} {} {})", x.to_infix(), op.name, y.to_infix()),
+                [ x, y, z ]    => format!("({} ? {} : {})", x.to_infix(), y.to_infix(), z.to_infix()),
+                _              => format_call(op.name, args),
+            }
+        }
+    }
+
+
+    fn rewrite_with<F: FnMut(&mut ExpressionNode)>(&mut self, f: &mut F) {
+        match self {
+            ExpressionNode::Constant{ .. } | ExpressionNode::Integer{ .. } => {},
+
+            ExpressionNode::Operator{ args, .. } | ExpressionNode::Function{ args, .. } => {
+                for arg in args {
+                    arg.rewrite_with(f);
+                }
+            }
+        }
+
+        f(self);
+    }
+}
+
+
+// Result of integer mode evaluation, which only uses f64 when a value can't be an exact integer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+}
+
+
+impl Value {
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Value::Integer(value) => value as f64,
+            Value::Float(value) => value,
+        }
+    }
+
+
+    // Whole numbers that f64 holds exactly also count as integers, so results such as 2^10 or
+    // abs(-3) can take part in integer arithmetic.
+    pub fn to_integer(self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(value),
+            Value::Float(value) if value.fract() == 0.0 && value.abs() <= ops::MAX_EXACT_INTEGER => Some(value as i64),
+            Value::Float(_) => None,
+        }
+    }
+}
+
+
 // A user defined function consists of an expression tree plus list of parameter names.
 #[derive(Debug)]
 pub struct Function {
@@ -26,11 +116,97 @@ pub struct Function {
 }
 
 
+impl Function {
+    // Renders the function body as text.
+    pub fn body(&self) -> String {
+        self.expression.to_string()
+    }
+
+
+    // Removes identity operations such as x+0 or x*1, so hot recursive functions do less work.
+    pub fn simplify(&mut self) {
+        self.expression.rewrite(|node| {
+            if let Some(simplified) = simplify_identity(node) {
+                *node = simplified;
+            }
+        });
+    }
+
+
+    // Best effort check for definitions like f(x) = f(x) + 1, where every evaluation path calls
+    // the function again so it can never terminate. Self calls that are guarded by a ternary or
+    // short circuit logical operator are assumed to have a base case, even if it is unreachable.
+    pub fn always_recurses(&self, name: &str) -> bool {
+        // If a parameter shadows the function name, the function cannot refer to itself.
+        !self.args.iter().any(|arg| arg == name) && always_calls(&self.expression, name)
+    }
+}
+
+
+// Simplifies a single binary operator whose argument is an identity value. x*0 only becomes 0 if x
+// is a finite constant, since NaN or infinity times zero is NaN. x^0 is always 1, matching powf.
+// Operands removed by simplification are never evaluated, so any error they would raise is lost.
+fn simplify_identity(node: &mut ExpressionNode) -> Option<ExpressionNode> {
+    let (op, args) = match node {
+        ExpressionNode::Operator{ op, args } if args.len() == 2 => (*op, args),
+        _ => return None,
+    };
+
+    fn is_constant(arg: &ExpressionNode, expected: f64) -> bool {
+        matches!(arg, ExpressionNode::Constant{ value } if *value == expected)
+    }
+
+    fn is_finite(arg: &ExpressionNode) -> bool {
+        matches!(arg, ExpressionNode::Constant{ value } if value.is_finite())
+    }
+
+    let zero = || ExpressionNode::Constant { value: 0.0 };
+    let one  = || ExpressionNode::Constant { value: 1.0 };
+
+    match op.name {
+        "+" if is_constant(&args[1], 0.0)                        => Some(args.remove(0)),
+        "+" if is_constant(&args[0], 0.0)                        => Some(args.remove(1)),
+        "-" if is_constant(&args[1], 0.0)                        => Some(args.remove(0)),
+        "*" if is_constant(&args[1], 1.0)                        => Some(args.remove(0)),
+        "*" if is_constant(&args[0], 1.0)                        => Some(args.remove(1)),
+        "*" if is_constant(&args[1], 0.0) && is_finite(&args[0]) => Some(zero()),
+        "*" if is_constant(&args[0], 0.0) && is_finite(&args[1]) => Some(zero()),
+        "/" if is_constant(&args[1], 1.0)                        => Some(args.remove(0)),
+        "^" if is_constant(&args[1], 1.0)                        => Some(args.remove(0)),
+        "^" if is_constant(&args[1], 0.0)                        => Some(one()),
+        _ => None,
+    }
+}
+
+
+fn always_calls(expression: &ExpressionNode, name: &str) -> bool {
+    match expression {
+        ExpressionNode::Constant{ .. } | ExpressionNode::Integer{ .. } => false,
+
+        // Function arguments are evaluated before the call, so a self call in any of them counts.
+        ExpressionNode::Function{ name: function_name, args } => {
+            function_name == name || args.iter().any(|arg| always_calls(arg, name))
+        }
+
+        ExpressionNode::Operator{ op, args } => match op.function {
+            // The first argument of a lazy operator is always evaluated. Ternaries then
+            // evaluate one of two branches, while || and && might not evaluate anything else.
+            OpFunction::Lazy(_) => {
+                always_calls(&args[0], name) || (args.len() == 3 && always_calls(&args[1], name) && always_calls(&args[2], name))
+            }
+
+            _ => args.iter().any(|arg| always_calls(arg, name))
+        }
+    }
+}
+
+
 // Local context used while evaluating a function.
 struct FunctionFrame<'a> {
 
-    // Backlink to the global execution context.
-    context: &'a Context,
+    // Backlink to the global execution context. This is mutable because assignments
+    // inside an expression, such as (x = 5) + x, define new variables as they evaluate.
+    context: &'a mut Context,
     
     // Parameter names and values for the currently executing function.
     local_names: &'a Vec<String>,
@@ -38,10 +214,10 @@ struct FunctionFrame<'a> {
 
     // Track recursion depth, so we can error out if it goes too far.
     recursion_count: u32,
-}
-
 
-const MAX_RECURSION: u32 = 256;
+    // When evaluation is abandoned, if the timeout setting is enabled.
+    deadline: Option<Instant>,
+}
 
 
 // Expression tree formatter, useful for debugging and unit tests.
@@ -56,6 +232,7 @@ impl fmt::Display for ExpressionNode {
 
         match self {
             ExpressionNode::Constant{ value      } => write!(f, "{}", value),
+            ExpressionNode::Integer { value      } => write!(f, "{}", value),
             ExpressionNode::Operator{ op,   args } => write!(f, "{}({})", op.name, format_args(args)),
             ExpressionNode::Function{ name, args } => write!(f, "{}({})", name,    format_args(args)),
         }
@@ -63,21 +240,36 @@ impl fmt::Display for ExpressionNode {
 }
 
 
+// Controls how the parser decides it has reached the end of an expression.
+#[derive(Clone, Copy)]
+enum Termination {
+    // Top level expressions run until the input runs out.
+    TopLevel,
+
+    // Parsing x or y from something like f(x, y(z)) stops at the closing parenthesis.
+    Nested,
+
+    // Clauses of a piecewise definition stop at the next separator operator, or when the input runs out.
+    Separator(&'static str),
+}
+
+
 // The parser turns a series of tokens into an expression tree.
 struct Parser {
     current: Option<ExpressionNode>,
     stack: Vec<(OperatorRef, Option<ExpressionNode>)>,
+    implicit_multiply: bool,
 }
 
 
 impl Parser {
     // Pushes a numeric constant onto the stack.
-    fn push_constant(&mut self, value: f64) -> Result<(), String> {
+    fn push_constant(&mut self, constant: ExpressionNode) -> Result<(), String> {
         if self.current.is_some() {
-            return Err(format!("Invalid expression: expecting operator but got '{}'.", value));
+            return Err(format!("Invalid expression: expecting operator but got '{}'.", constant));
         }
 
-        self.current = Some(ExpressionNode::Constant { value });
+        self.current = Some(constant);
         
         Ok(())
     }
@@ -85,16 +277,25 @@ impl Parser {
 
     // Pushes a symbol reference (variable or function call) onto the stack.
     fn push_symbol(&mut self, symbol: &str, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+        // Keyword operators take priority over builtin and user functions.
+        if let Some(op) = find_keyword(symbol) {
+            return self.push_operator_token(op, tokenizer);
+        }
+
         if self.current.is_some() {
-            return Err(format!("Invalid expression: expecting operator but got '{}'.", symbol));
+            if self.implicit_multiply {
+                self.push_implicit_multiply()?;
+            } else {
+                return Err(format!("Invalid expression: expecting operator but got '{}'.", symbol));
+            }
         }
 
-        let args = Parser::parse_arguments(tokenizer)?;
+        let args = Parser::parse_arguments(tokenizer, self.implicit_multiply)?;
 
         match ops::find_function(symbol) {
             Some(op) => {
-                if args.len() != op.arity as usize {
-                    return Err(format!("Wrong number of arguments for {}(): expected {} but got {}.", op.name, op.arity, args.len()));
+                if !op.accepts_arity(args.len()) {
+                    return Err(format!("Wrong number of arguments for {}(): expected {} but got {}.", op.name, op.describe_arity(), args.len()));
                 }
                 
                 self.current = Some(ExpressionNode::Operator { op, args });
@@ -109,6 +310,24 @@ impl Parser {
     }
 
 
+    // Pushes an operator read from the input. The % symbol is ambiguous: it means modulo when used
+    // between two operands, as in 10 % 3, but percent (dividing by 100) when it follows a value
+    // and is not followed by the start of another operand, as in 10% * 3 or max(50%, x). Operands
+    // start with a number, symbol, open parenthesis, or prefix operator, so 10% - 3 is modulo.
+    fn push_operator_token(&mut self, op: OperatorRef, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+        if op == "%" && self.current.is_some() && !Parser::peek_operand(tokenizer) {
+            // Postfix percent binds tighter than anything else, so applies directly to the current value.
+            let current = self.current.take().unwrap();
+
+            self.current = Some(ExpressionNode::Operator { op: &ops::PERCENT, args: vec![ current ] });
+
+            Ok(())
+        } else {
+            self.push_operator(op)
+        }
+    }
+
+
     // Pushes an operator onto the stack, performing a shift/reduce loop based on precedence.
     fn push_operator(&mut self, mut op: OperatorRef) -> Result<(), String> {
         // Turn binary subtraction into unary negation if there is no current value.
@@ -116,6 +335,11 @@ impl Parser {
             op = &ops::NEGATE;
         }
 
+        // With implicit multiplication enabled, 3(4+5) means 3*(4+5).
+        if op == "(" && self.current.is_some() && self.implicit_multiply {
+            self.push_implicit_multiply()?;
+        }
+
         // Reduce the operator stack according to precedence.
         if op != "(" {
             let op_precedence = op.precedence as u32;
@@ -193,6 +417,12 @@ impl Parser {
     }
 
 
+    // Inserts a multiply between a value and a directly following symbol or parenthesis, so 2pi means 2*pi.
+    fn push_implicit_multiply(&mut self) -> Result<(), String> {
+        self.push_operator(ops::find_operator("*").unwrap())
+    }
+
+
     // Decides whether we are dealing with a binary or ternary operator.
     fn binary_or_ternary(op: OperatorRef, x: ExpressionNode, mut y: ExpressionNode) -> ExpressionNode {
         if op == "?" {
@@ -213,14 +443,21 @@ impl Parser {
 
 
     // Parses the arguments of a function call.
-    fn parse_arguments(tokenizer: &mut Peekable<Tokenizer>) -> Result<Vec<ExpressionNode>, String> {
+    fn parse_arguments(tokenizer: &mut Peekable<Tokenizer>, implicit_multiply: bool) -> Result<Vec<ExpressionNode>, String> {
         let mut args = vec![];
 
         if Parser::peek_operator(tokenizer, "(") {
             tokenizer.next();
 
+            // Each argument swallows its terminating comma, so a trailing comma before ) is ignored.
             while !Parser::peek_operator(tokenizer, ")") {
-                args.push(parse(tokenizer, true)?);
+                if let Some(Ok(Token::Text(","))) = tokenizer.peek() {
+                    return Err(String::from("Empty argument in function call."));
+                }
+
+                Parser::check_function_argument(tokenizer)?;
+
+                args.push(parse_until(tokenizer, Termination::Nested, implicit_multiply)?);
             }
 
             tokenizer.next();
@@ -230,24 +467,64 @@ impl Parser {
     }
 
 
+    // Rejects a builtin function passed by name as an argument, such as f(sin), which would otherwise
+    // report that sin() was called with the wrong number of arguments. User functions are checked
+    // during evaluation, since they may not be defined yet.
+    fn check_function_argument(tokenizer: &Peekable<Tokenizer>) -> Result<(), String> {
+        let mut lookahead = tokenizer.clone();
+
+        if let Some(Ok(Token::Text(name))) = lookahead.next() {
+            let is_bare = matches!(lookahead.peek(), Some(Ok(Token::Text(",")))) || Parser::peek_operator(&mut lookahead, ")");
+
+            if is_bare && ops::find_function(name).is_some_and(|op| op.min_arity > 0) {
+                return Err(format!("Cannot pass function {} as a value.", name));
+            }
+        }
+
+        Ok(())
+    }
+
+
     // Decide whether we've reached the end of the expression.
-    fn is_finished(&self, tokenizer: &mut Peekable<Tokenizer>, is_nested: bool) -> bool {
+    fn is_finished(&self, tokenizer: &mut Peekable<Tokenizer>, termination: Termination) -> bool {
         if let Some(Ok(Token::Text(","))) = tokenizer.peek() {
             // Commas always terminate.
             tokenizer.next();
-            true
+            return true;
         }
-        else if is_nested {
-            // Parsing x or y from something like f(x, y(z)).
-            // Closing parenthesis terminates only if there are no open parens on the stack.
-            if Parser::peek_operator(tokenizer, ")") {
-                !self.stack.iter().any(|op| op.0 == "(")
-            } else {
-                false
+
+        match termination {
+            Termination::TopLevel => {
+                // When parsing a top level expression, we're done if the input runs out. A trailing
+                // semicolon also ends the expression, but is left for the caller to see it was there.
+                tokenizer.peek().is_none() || (matches!(tokenizer.peek(), Some(Ok(Token::Text(";")))) && !self.has_open_paren())
+            }
+
+            Termination::Nested => {
+                // Closing parenthesis terminates only if there are no open parens on the stack.
+                Parser::peek_operator(tokenizer, ")") && !self.has_open_paren()
+            }
+
+            Termination::Separator(separator) => {
+                // Likewise the separator, although running out of input is also fine.
+                tokenizer.peek().is_none() || (Parser::peek_operator(tokenizer, separator) && !self.has_open_paren())
             }
-        } else {
-            // When parsing a top level expression, we're done if the input runs out.
-            tokenizer.peek().is_none()
+        }
+    }
+
+
+    fn has_open_paren(&self) -> bool {
+        self.stack.iter().any(|op| op.0 == "(")
+    }
+
+
+    // Checks whether the next token can start an operand.
+    fn peek_operand(tokenizer: &mut Peekable<Tokenizer>) -> bool {
+        match tokenizer.peek() {
+            None                          => false,
+            Some(Ok(Token::Text(",")))    => false,
+            Some(Ok(Token::Operator(op))) => ["(", "-", "!", "~"].contains(&op.name),
+            Some(_)                       => true,
         }
     }
 
@@ -263,19 +540,36 @@ impl Parser {
 
 
 // Expression parser entrypoint.
-pub fn parse(tokenizer: &mut Peekable<Tokenizer>, is_nested: bool) -> Result<ExpressionNode, String>
+pub fn parse(tokenizer: &mut Peekable<Tokenizer>, is_nested: bool, settings: &Settings) -> Result<ExpressionNode, String>
+{
+    let implicit_multiply = settings.implicit_multiply;
+
+    if is_nested {
+        parse_until(tokenizer, Termination::Nested, implicit_multiply)
+    } else if is_piecewise_definition(tokenizer) {
+        parse_piecewise_definition(tokenizer, implicit_multiply)
+    } else {
+        parse_until(tokenizer, Termination::TopLevel, implicit_multiply)
+    }
+}
+
+
+fn parse_until(tokenizer: &mut Peekable<Tokenizer>, termination: Termination, implicit_multiply: bool) -> Result<ExpressionNode, String>
 {
     let mut parser = Parser {
         current: None,
         stack: vec![],
+        implicit_multiply,
     };
 
-    while !parser.is_finished(tokenizer, is_nested) {
+    while !parser.is_finished(tokenizer, termination) {
         match tokenizer.next() {
             Some(token) => match token? {
-                Token::Number(value) => parser.push_constant(value)?,
-                Token::Text(value)   => parser.push_symbol(value, tokenizer)?,
-                Token::Operator(op)  => parser.push_operator(op)?,
+                Token::Number(value)  => parser.push_constant(ExpressionNode::Constant { value })?,
+                Token::Integer(value) => parser.push_constant(ExpressionNode::Integer { value })?,
+                Token::Text(value)    => parser.push_symbol(value, tokenizer)?,
+                Token::Str(value)     => return Err(format!("Invalid expression: unexpected string {:?}.", value)),
+                Token::Operator(op)   => parser.push_operator_token(op, tokenizer)?,
             },
             None => return Err(String::from("Invalid expression: unexpected end of input.")),
         }
@@ -297,41 +591,245 @@ pub fn parse(tokenizer: &mut Peekable<Tokenizer>, is_nested: bool) -> Result<Exp
 }
 
 
+// Looks ahead to check for a piecewise definition such as f(x) | x<0 = -x | x>=0 = x.
+// That needs a function name, optional parameter list, | separator, and an = somewhere after it.
+fn is_piecewise_definition(tokenizer: &Peekable<Tokenizer>) -> bool {
+    let mut lookahead = tokenizer.clone();
+
+    if !matches!(lookahead.next(), Some(Ok(Token::Text(_)))) {
+        return false;
+    }
+
+    if Parser::peek_operator(&mut lookahead, "(") {
+        lookahead.next();
+
+        loop {
+            match lookahead.next() {
+                Some(Ok(Token::Operator(op))) if op == ")" => break,
+                Some(Ok(Token::Text(_))) => {},
+                _ => return false,
+            }
+        }
+    }
+
+    if !Parser::peek_operator(&mut lookahead, "|") {
+        return false;
+    }
+
+    let mut depth = 0;
+
+    for token in lookahead {
+        match token {
+            Ok(Token::Operator(op)) if op == "(" => depth += 1,
+            Ok(Token::Operator(op)) if op == ")" => depth -= 1,
+            Ok(Token::Operator(op)) if op == "=" && depth == 0 => return true,
+            Ok(Token::Text(",")) if depth == 0 => return false,
+            Err(_) => return false,
+            _ => {},
+        }
+    }
+
+    false
+}
+
+
+// Parses a piecewise definition into a chain of ternary operators, which evaluates the
+// value of the first clause whose condition is true. If no condition matches, the result is NaN.
+// Clauses are separated by |, so any bitwise or inside a clause must be parenthesized.
+fn parse_piecewise_definition(tokenizer: &mut Peekable<Tokenizer>, implicit_multiply: bool) -> Result<ExpressionNode, String> {
+    let name = match tokenizer.next() {
+        Some(Ok(Token::Text(name))) => String::from(name),
+        _ => unreachable!(),
+    };
+
+    let head = ExpressionNode::Function { name, args: Parser::parse_arguments(tokenizer, implicit_multiply)? };
+
+    let mut clauses = vec![];
+
+    while Parser::peek_operator(tokenizer, "|") {
+        tokenizer.next();
+
+        let condition = parse_until(tokenizer, Termination::Separator("="), implicit_multiply)?;
+
+        if !Parser::peek_operator(tokenizer, "=") {
+            return Err(String::from("Invalid piecewise definition: expecting '=' after condition."));
+        }
+
+        tokenizer.next();
+
+        let value = parse_until(tokenizer, Termination::Separator("|"), implicit_multiply)?;
+
+        clauses.push((condition, value));
+    }
+
+    let body = clauses.into_iter().rev().fold(ExpressionNode::Constant { value: f64::NAN }, |otherwise, (condition, value)| {
+        ExpressionNode::Operator { op: &ops::TERNARY, args: vec![ condition, value, otherwise ] }
+    });
+
+    Ok(ExpressionNode::Operator { op: ops::find_operator("=").unwrap(), args: vec![ head, body ] })
+}
+
+
 // Expression evaluator entrypoint.
-pub fn evaluate(expression: &ExpressionNode, context: &Context) -> Result<f64, String> {
-    let frame = FunctionFrame {
+pub fn evaluate(expression: &ExpressionNode, context: &mut Context) -> Result<f64, String> {
+    evaluate_with(expression, context, &[])
+}
+
+
+// Evaluates an expression, seeding the local frame with a set of named values.
+pub fn evaluate_with(expression: &ExpressionNode, context: &mut Context, bindings: &[(&str, f64)]) -> Result<f64, String> {
+    let deadline = context.settings.timeout.map(|timeout| Instant::now() + Duration::from_millis(timeout as u64));
+
+    let mut frame = FunctionFrame {
+        context,
+        local_names: &bindings.iter().map(|binding| String::from(binding.0)).collect(),
+        local_values: bindings.iter().map(|binding| binding.1).collect(),
+        recursion_count: 0,
+        deadline,
+    };
+    
+    eval(expression, &mut frame)
+}
+
+
+// Integer mode evaluation keeps operators that have an exact integer equivalent in i64 arithmetic,
+// so results beyond 2^53 keep every digit. Each operator falls back to f64 if an operand is not a
+// whole number or the integer result overflows. Everything else is evaluated as usual.
+pub fn evaluate_exact(expression: &ExpressionNode, context: &mut Context) -> Result<Value, String> {
+    let deadline = context.settings.timeout.map(|timeout| Instant::now() + Duration::from_millis(timeout as u64));
+
+    let mut frame = FunctionFrame {
         context,
         local_names: &vec![],
         local_values: vec![],
         recursion_count: 0,
+        deadline,
     };
-    
-    eval(expression, &frame)
+
+    eval_exact(expression, &mut frame)
+}
+
+
+fn eval_exact(expression: &ExpressionNode, frame: &mut FunctionFrame) -> Result<Value, String> {
+    match expression {
+        ExpressionNode::Integer{ value } => Ok(Value::Integer(*value)),
+
+        ExpressionNode::Operator{ op, args } if args.len() == 1 && *op == "-" => {
+            let value = eval_exact(&args[0], frame)?;
+
+            match value.to_integer().and_then(i64::checked_neg) {
+                Some(result) => Ok(Value::Integer(result)),
+                None => Ok(Value::Float(-value.to_f64())),
+            }
+        }
+
+        ExpressionNode::Operator{ op, args } if args.len() == 2 && INTEGER_OPERATORS.contains(&op.name) => {
+            let (x, y) = (eval_exact(&args[0], frame)?, eval_exact(&args[1], frame)?);
+
+            if let Some(result) = x.to_integer().zip(y.to_integer()).and_then(|(x, y)| integer_operator(op.name, x, y)) {
+                return Ok(Value::Integer(result));
+            }
+
+            // Otherwise apply the operator to f64 values, converting them just as evaluate_operator would.
+            let (x, y) = (convert_argument(op, x.to_f64(), frame.context)?, convert_argument(op, y.to_f64(), frame.context)?);
+
+            match op.function {
+                OpFunction::Binary(function) => {
+                    check_divide_by_zero(op, y, frame.context)?;
+                    Ok(Value::Float(function(x, y)))
+                }
+
+                OpFunction::Configurable(function) => Ok(Value::Float(function(&frame.context.settings, x, y))),
+
+                _ => Err(format!("Invalid use of {} operator.", op.name)),
+            }
+        }
+
+        // Variables holding large integers, such as Ans after an integer mode result, keep every digit.
+        ExpressionNode::Function{ name, args } if args.is_empty() => {
+            match frame.context.functions.get(name).map(|function| (&function.expression, function.args.is_empty())) {
+                Some((ExpressionNode::Integer{ value }, true)) => Ok(Value::Integer(*value)),
+                _ => eval(expression, frame).map(Value::Float),
+            }
+        }
+
+        _ => eval(expression, frame).map(Value::Float),
+    }
+}
+
+
+// Binary operators that integer mode evaluates using i64 arithmetic.
+const INTEGER_OPERATORS: [&str; 9] = [ "+", "-", "*", "%", "<<", ">>", "&", "|", "^^" ];
+
+
+// Returns None if the result can't be represented, so the caller falls back to f64. Unlike the
+// f64 versions, bitwise operators work on all 64 bits and >> is an arithmetic shift.
+fn integer_operator(name: &str, x: i64, y: i64) -> Option<i64> {
+    match name {
+        "+"  => x.checked_add(y),
+        "-"  => x.checked_sub(y),
+        "*"  => x.checked_mul(y),
+        "%"  => x.checked_rem_euclid(y),
+        "<<" if (0..64).contains(&y) => i64::try_from((x as i128) << y).ok(),
+        ">>" if (0..64).contains(&y) => Some(x >> y),
+        "&"  => Some(x & y),
+        "|"  => Some(x | y),
+        "^^" => Some(x ^ y),
+        _    => None,
+    }
 }
 
 
 // Recursive expression evaluator.
-fn eval(expression: &ExpressionNode, frame: &FunctionFrame) -> Result<f64, String> {
+fn eval(expression: &ExpressionNode, frame: &mut FunctionFrame) -> Result<f64, String> {
     match expression {
         ExpressionNode::Constant{ value      } => Ok(*value),
-        ExpressionNode::Operator{ op, args   } => evaluate_operator(op, args, frame),
+        ExpressionNode::Integer { value      } => Ok(*value as f64),
+        ExpressionNode::Operator{ op, args   } => evaluate_operator(op, args, frame).map(|value| angle_result(op, value, frame.context)),
         ExpressionNode::Function{ name, args } => evaluate_function(name, args, frame),
     }
 }
 
 
-fn evaluate_operator(op: OperatorRef, args: &[ExpressionNode], frame: &FunctionFrame) -> Result<f64, String> {
+fn evaluate_operator(op: OperatorRef, args: &[ExpressionNode], frame: &mut FunctionFrame) -> Result<f64, String> {
     match op.function {
         // No need for bound checks because the parser never outputs operators with wrong argument count.
         OpFunction::Nullary(function) => Ok(function()),
-        OpFunction::Unary  (function) => Ok(function(eval(&args[0], frame)?)),
-        OpFunction::Binary (function) => Ok(function(eval(&args[0], frame)?, eval(&args[1], frame)?)),
+        OpFunction::Unary  (function) => Ok(function(eval_argument(op, &args[0], frame)?)),
+        OpFunction::Binary (function) => {
+            let (x, y) = (eval_argument(op, &args[0], frame)?, eval_argument(op, &args[1], frame)?);
+            check_divide_by_zero(op, y, frame.context)?;
+            Ok(function(x, y))
+        },
+
+        OpFunction::Configurable(function) => {
+            let (x, y) = (eval_argument(op, &args[0], frame)?, eval_argument(op, &args[1], frame)?);
+            Ok(function(&frame.context.settings, x, y))
+        },
+
+        OpFunction::ConfigurableUnary(function) => {
+            let x = eval_argument(op, &args[0], frame)?;
+            Ok(function(&frame.context.settings, x))
+        },
+
+        OpFunction::Ternary(function) => {
+            let (x, y, z) = (eval_argument(op, &args[0], frame)?, eval_argument(op, &args[1], frame)?, eval_argument(op, &args[2], frame)?);
+            Ok(function(x, y, z))
+        },
+
+        OpFunction::Variadic(function) => {
+            let values = args.iter().map(|arg| eval_argument(op, arg, frame)).collect::<Result<Vec<f64>, String>>()?;
+            Ok(function(&values))
+        },
 
         OpFunction::Lazy(function) => {
             // Used by the ||, &&, and ?: operators. A function applied to the first
             // argument indicates which of the arguments to return. Unused arguments
             // are never evaluated. This lazy evaluation enables recursive functions.
             let arg0 = eval(&args[0], frame)?;
+
+            check_condition(arg0, frame.context)?;
+
             let which_arg = function(arg0);
             
             if which_arg == 0 {
@@ -341,12 +839,118 @@ fn evaluate_operator(op: OperatorRef, args: &[ExpressionNode], frame: &FunctionF
             }
         },
 
+        OpFunction::Invalid if op == "=" => evaluate_assignment(args, frame),
+
         OpFunction::Invalid => Err(format!("Invalid use of {} operator.", op.name))
     }
 }
 
 
-fn evaluate_function(name: &str, args: &[ExpressionNode], frame: &FunctionFrame) -> Result<f64, String> {
+// Top level x=y and f(x)=y definitions are handled by deconstruct_function_definition, so
+// this is only reached for assignments nested inside a larger expression, such as (x = 5) + x,
+// or ones whose left side is not a name at all, such as 2 + x = 3. Nested assignments evaluate
+// their right hand side immediately, storing the result as a variable.
+fn evaluate_assignment(args: &[ExpressionNode], frame: &mut FunctionFrame) -> Result<f64, String> {
+    let name = match &args[0] {
+        ExpressionNode::Function{ name, args } if args.is_empty() => name,
+        ExpressionNode::Function{ .. } => return Err(String::from("Only simple variables can be assigned inside an expression.")),
+        ExpressionNode::Operator{ op, .. } if is_reserved_name(op.name) => return Err(check_function_name(op.name).unwrap_err()),
+        _ => return Err(String::from("Assignment must be at the start of a definition (name = ... or f(args) = ...).")),
+    };
+
+    if frame.local_names.contains(name) {
+        return Err(format!("Cannot assign to function parameter {}.", name));
+    }
+
+    check_function_name(name)?;
+
+    let value = eval(&args[1], frame)?;
+
+    frame.context.set_function(name.clone(), Function {
+        expression: ExpressionNode::Constant { value },
+        args: vec![],
+    });
+
+    Ok(value)
+}
+
+
+// Operators that work on integers normally truncate fractional arguments, but can be configured to round them.
+fn eval_argument(op: OperatorRef, arg: &ExpressionNode, frame: &mut FunctionFrame) -> Result<f64, String> {
+    if matches!(op.precedence, ops::Precedence::None) {
+        check_function_value(arg, frame)?;
+    }
+
+    let value = eval(arg, frame)?;
+
+    convert_argument(op, value, frame.context)
+}
+
+
+// Applies whatever checks and conversions an operator expects of an already evaluated argument.
+fn convert_argument(op: OperatorRef, value: f64, context: &Context) -> Result<f64, String> {
+    if op.takes_conditions() {
+        check_condition(value, context)?;
+    }
+
+    if op.takes_angle() && context.settings.angle_unit == AngleUnit::Degrees {
+        Ok(value.to_radians())
+    } else if op.has_integer_arguments() && context.settings.integer_conversion == IntegerConversion::Round {
+        Ok(value.round())
+    } else {
+        Ok(value)
+    }
+}
+
+
+// Inverse trig functions compute radians, which are converted to degrees if that is the angle unit.
+fn angle_result(op: OperatorRef, value: f64, context: &Context) -> f64 {
+    if op.returns_angle() && context.settings.angle_unit == AngleUnit::Degrees {
+        value.to_degrees()
+    } else {
+        value
+    }
+}
+
+
+// A user function that takes parameters can't be passed by name as an argument, as in max(f, 1),
+// since there are no first class function values. Without this check the call to f would instead
+// report that it was given the wrong number of arguments.
+fn check_function_value(arg: &ExpressionNode, frame: &FunctionFrame) -> Result<(), String> {
+    if let ExpressionNode::Function{ name, args } = arg {
+        let is_function = args.is_empty() && !frame.local_names.contains(name) &&
+                          frame.context.functions.get(name).is_some_and(|function| !function.args.is_empty());
+
+        if is_function {
+            return Err(format!("Cannot pass function {} as a value.", name));
+        }
+    }
+
+    Ok(())
+}
+
+
+// Using NaN as a condition normally counts as false, but can be configured to raise an error.
+fn check_condition(value: f64, context: &Context) -> Result<(), String> {
+    if value.is_nan() && context.settings.strict {
+        Err(String::from("NaN is not a valid condition."))
+    } else {
+        Ok(())
+    }
+}
+
+
+// Division and modulo by zero normally return infinity or NaN, but can be configured to raise an error.
+fn check_divide_by_zero(op: OperatorRef, divisor: f64, context: &Context) -> Result<(), String> {
+    if divisor == 0.0 && context.settings.divide_by_zero_is_error && [ "/", "%", "idiv", "imod" ].contains(&op.name) {
+        Err(String::from("Division by zero."))
+    } else {
+        Ok(())
+    }
+}
+
+
+fn evaluate_function(name: &str, args: &[ExpressionNode], frame: &mut FunctionFrame) -> Result<f64, String> {
     if let Some(which_local) = frame.local_names.iter().position(|local_name| { local_name == name }) {
         // Looking up a local function parameter.
         if args.is_empty() {
@@ -356,7 +960,8 @@ fn evaluate_function(name: &str, args: &[ExpressionNode], frame: &FunctionFrame)
         }
     } else {
         // Calling a user defined function.
-        match frame.context.functions.get(name) {
+        // Clone the Rc so the function stays alive even if evaluating it reassigns the variable.
+        match frame.context.functions.get(name).cloned() {
             Some(function) => {
                 if args.len() != function.args.len() {
                     return Err(format!("Wrong number of arguments for {}(): expected {} but got {}.", name, function.args.len(), args.len()));
@@ -365,25 +970,174 @@ fn evaluate_function(name: &str, args: &[ExpressionNode], frame: &FunctionFrame)
                 let mut child_args = Vec::with_capacity(args.len());
                 
                 for arg in args {
+                    check_function_value(arg, frame)?;
                     child_args.push(eval(arg, frame)?);
                 }
      
-                if frame.recursion_count > MAX_RECURSION {
-                    return Err(String::from("Excessive recursion."));
-                }
-     
-                let child_frame = FunctionFrame {
-                    context: frame.context,
-                    local_names: &function.args,
-                    local_values: child_args,
-                    recursion_count: frame.recursion_count + 1,
-                };
-                
-                eval(&function.expression, &child_frame)
+                call_function(name, &function, child_args, frame)
             },
-            
-            None => Err(format!("Unknown value {}.", name))
+
+            None if name == "ans" => evaluate_ans(args, frame),
+
+            None => match find_higher_order_function(name) {
+                Some(higher_order) => evaluate_higher_order_function(higher_order, args, frame),
+                None => Err(format!("Unknown value {}.", name))
+            }
+        }
+    }
+}
+
+
+// Invokes a user defined function, given the already evaluated values of its arguments.
+fn call_function(name: &str, function: &Function, arg_values: Vec<f64>, frame: &mut FunctionFrame) -> Result<f64, String> {
+    // Memoized functions look up their arguments by bit pattern, so NaN and -0 are cached correctly.
+    let memo_key = if frame.context.memo_caches.contains_key(name) {
+        let key: Vec<u64> = arg_values.iter().map(|value| value.to_bits()).collect();
+
+        if let Some(&value) = frame.context.memo_caches[name].get(&key) {
+            return Ok(value);
         }
+
+        Some(key)
+    } else {
+        None
+    };
+
+    if frame.recursion_count >= frame.context.settings.recursion_limit {
+        return Err(format!("Excessive recursion in {}() at depth {}.", name, frame.recursion_count));
+    }
+
+    // Checking the clock on every call is cheap compared to evaluating the function body.
+    if frame.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(String::from("Evaluation timed out."));
+    }
+
+    let mut child_frame = FunctionFrame {
+        context: frame.context,
+        local_names: &function.args,
+        local_values: arg_values,
+        recursion_count: frame.recursion_count + 1,
+        deadline: frame.deadline,
+    };
+    
+    #[cfg(test)]
+    {
+        child_frame.context.function_calls += 1;
+    }
+
+    let value = eval(&function.expression, &mut child_frame)?;
+
+    if let Some(key) = memo_key {
+        if let Some(cache) = frame.context.memo_caches.get_mut(name) {
+            cache.insert(key, value);
+        }
+    }
+
+    Ok(value)
+}
+
+
+// Operators spelled as words, paired with the symbolic operator they are aliases for. The tokenizer
+// reads these as barewords, so push_symbol resolves them before considering user functions, and
+// they can never be used as function names.
+pub static KEYWORDS: [(&str, &str); 3] = [
+    ( "and", "&&" ),
+    ( "or",  "||" ),
+    ( "not", "!"  ),
+];
+
+
+fn find_keyword(name: &str) -> Option<OperatorRef> {
+    KEYWORDS.iter().find(|keyword| keyword.0 == name).and_then(|keyword| ops::find_operator(keyword.1))
+}
+
+
+// Names with a builtin meaning that user functions would conflict with.
+pub fn is_reserved_name(name: &str) -> bool {
+    find_keyword(name).is_some() || name == "ans" || find_higher_order_function(name).is_some() || ops::find_function(name).is_some()
+}
+
+
+pub fn check_function_name(name: &str) -> Result<(), String> {
+    if is_reserved_name(name) {
+        Err(format!("Cannot define {}: that name is reserved.", name))
+    } else {
+        Ok(())
+    }
+}
+
+
+// Builtins such as deriv(f, x) take the name of a user defined function of one parameter as their
+// first argument, followed by a fixed number of regular values. These are special forms, because
+// the evaluator does not otherwise support passing functions as values.
+pub struct HigherOrderFunction {
+    pub name:  &'static str,
+    pub arity: usize,
+    function:  fn(&mut calculus::Function, &[f64]) -> Result<f64, String>,
+}
+
+
+pub static HIGHER_ORDER_FUNCTIONS: [HigherOrderFunction; 3] = [
+    HigherOrderFunction { name: "deriv", arity: 1, function: |f, args| calculus::derivative(f, args[0])         },
+    HigherOrderFunction { name: "integ", arity: 2, function: |f, args| calculus::integral(f, args[0], args[1]) },
+    HigherOrderFunction { name: "solve", arity: 2, function: |f, args| calculus::root(f, args[0], args[1])     },
+];
+
+
+fn find_higher_order_function(name: &str) -> Option<&'static HigherOrderFunction> {
+    HIGHER_ORDER_FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+
+fn evaluate_higher_order_function(higher_order: &HigherOrderFunction, args: &[ExpressionNode], frame: &mut FunctionFrame) -> Result<f64, String> {
+    let name = higher_order.name;
+
+    if args.len() != higher_order.arity + 1 {
+        return Err(format!("Wrong number of arguments for {}(): expected {} but got {}.", name, higher_order.arity + 1, args.len()));
+    }
+
+    // The first argument must be a bare name that refers to a user defined function.
+    let function_name = match &args[0] {
+        ExpressionNode::Function{ name: function_name, args: function_args } if function_args.is_empty() && !frame.local_names.contains(function_name) => function_name,
+        _ => return Err(format!("First argument of {}() must be the name of a function.", name)),
+    };
+
+    let function = match frame.context.functions.get(function_name).cloned() {
+        Some(function) => function,
+        None => return Err(format!("Unknown function {}.", function_name)),
+    };
+
+    if function.args.len() != 1 {
+        return Err(format!("{}() requires a function of one parameter, but {}() has {}.", name, function_name, function.args.len()));
+    }
+
+    let mut arg_values = Vec::with_capacity(higher_order.arity);
+
+    for arg in &args[1..] {
+        arg_values.push(eval(arg, frame)?);
+    }
+
+    (higher_order.function)(&mut |x| call_function(function_name, &function, vec![ x ], frame), &arg_values)
+}
+
+
+// Recalls a previous result: ans or ans(1) is the most recent, ans(2) the one before that, etc.
+fn evaluate_ans(args: &[ExpressionNode], frame: &mut FunctionFrame) -> Result<f64, String> {
+    let index = match args {
+        []      => 1.0,
+        [index] => eval(index, frame)?,
+        _       => return Err(format!("Wrong number of arguments for ans(): expected 0 or 1 but got {}.", args.len())),
+    };
+
+    if index < 1.0 || index.fract() != 0.0 {
+        return Err(String::from("ans() index must be a positive integer."));
+    }
+
+    let results = &frame.context.results;
+
+    match results.get(index as usize - 1) {
+        Some(value) => Ok(*value),
+        None => Err(format!("ans({}) is out of range: there are only {} previous results.", index, results.len())),
     }
 }
 
@@ -425,6 +1179,7 @@ pub fn deconstruct_function_definition(expression: &mut ExpressionNode) -> Optio
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::settings::IntegerWidth;
 
 
     #[test]
@@ -439,11 +1194,23 @@ mod tests {
         test_parse("1*(2+3)", "*(1,+(2,3))");
         test_parse("(1+2)*3", "*(+(1,2),3)");
         test_parse("1+2==3<sqrt(10)^5", "==(+(1,2),<(3,^(sqrt(10),5)))");
+        test_parse("1-2-3", "-(-(1,2),3)");
+        test_parse("2^3^4", "^(2,^(3,4))");
         test_parse("e", "e()");
         test_parse("sin(e())", "sin(e())");
         test_parse("max(1,2)", "max(1,2)");
         test_parse("foo(1,2,3,4)", "foo(1,2,3,4)");
         test_parse("foo(1,bar(x,y+foo(bar())))", "foo(1,bar(x(),+(y(),foo(bar()))))");
+        test_parse("3 <? 5 == 3", "==(<?(3,5),3)");
+        test_parse("1 <? 2 >? 3", ">?(<?(1,2),3)");
+        test_parse("1 < 2 >? 3 + 4", "<(1,>?(2,+(3,4)))");
+        test_parse("a<?b?c:d", "?:(<?(a(),b()),c(),d())");
+        test_parse("a || b ^| c && d", "||(a(),^|(b(),&&(c(),d())))");
+        test_parse("a ^| b ^| c", "^|(^|(a(),b()),c())");
+        test_parse("2^|x", "^|(2,x())");
+        test_parse("a -> b -> c", "->(a(),->(b(),c()))");
+        test_parse("a || b -> c && d", "->(||(a(),b()),&&(c(),d()))");
+        test_parse("a -> b ? c : d", "?:(->(a(),b()),c(),d())");
     }
 
 
@@ -457,6 +1224,29 @@ mod tests {
     }
 
 
+    #[test]
+    fn parse_keywords() {
+        for (keyword, symbol) in [ ("a and b", "a && b"), ("a or b", "a || b"), ("not a", "!a") ] {
+            assert_eq!(do_parse(keyword).unwrap().to_string(), do_parse(symbol).unwrap().to_string());
+        }
+
+        test_parse("a and b or c", "||(&&(a(),b()),c())");
+        test_parse("a or b and c", "||(a(),&&(b(),c()))");
+        test_parse("not a and b", "&&(!(a()),b())");
+        test_parse("not(a or b)", "!(||(a(),b()))");
+        test_parse("a == 1 and b < 2", "&&(==(a(),1),<(b(),2))");
+        test_parse("android + order + nothing", "+(+(android(),order()),nothing())");
+
+        test_parse_error("a and", "Invalid expression: unexpected end of input.");
+        test_parse_error("and b", "Invalid expression: binary && operator is missing an operand.");
+
+        assert_eq!(unwrap_eval("1 and 0"), 0.0);
+        assert_eq!(unwrap_eval("1 or 0"), 1.0);
+        assert_eq!(unwrap_eval("not 0"), 1.0);
+        assert_eq!(unwrap_eval("not 1 or 2 > 1"), 1.0);
+    }
+
+
     #[test]
     fn parse_ternary() {
         test_parse("1?2:3", "?:(1,2,3)");
@@ -464,15 +1254,80 @@ mod tests {
     }
 
 
+    #[test]
+    fn parse_percent() {
+        // Between two operands, % is modulo.
+        test_parse("10 % 3", "%(10,3)");
+        test_parse("10 % x", "%(10,x())");
+        test_parse("10 % (3)", "%(10,3)");
+        test_parse("10 % -3", "%(10,-(3))");
+        test_parse("10% - 3", "%(10,-(3))");
+
+        // Otherwise it is postfix percent, binding tighter than any other operator.
+        test_parse("50%", "%(50)");
+        test_parse("10% * 3", "*(%(10),3)");
+        test_parse("200 * 50%", "*(200,%(50))");
+        test_parse("2^50%", "^(2,%(50))");
+        test_parse("-50%", "-(%(50))");
+        test_parse("(1+2)%", "%(+(1,2))");
+        test_parse("x%%3", "%(%(x()),3)");
+        test_parse("max(50%, 1)", "max(%(50),1)");
+        test_parse("f(50%)", "f(%(50))");
+
+        test_parse_error("%", "Invalid expression: unexpected end of input.");
+        test_parse_error("% 3", "Invalid expression: binary % operator is missing an operand.");
+    }
+
+
+    #[test]
+    fn parse_piecewise() {
+        test_parse("f(x) | x<0 = -x | x>=0 = x", "=(f(x()),?:(<(x(),0),-(x()),?:(>=(x(),0),x(),NaN)))");
+        test_parse("f(x, y) | x>y = x | 1 = y", "=(f(x(),y()),?:(>(x(),y()),x(),?:(1,y(),NaN)))");
+        test_parse("f | x>0 && x<5 = (x|1) | x = 2", "=(f(),?:(&&(>(x(),0),<(x(),5)),|(x(),1),?:(x(),2,NaN)))");
+
+        // Without an = this is just a bitwise or.
+        test_parse("x | y", "|(x(),y())");
+        test_parse("f(x) | 3, y = 2", "|(f(x()),3)");
+
+        test_parse_error("f(x) | x<0 = -x | x>0", "Invalid piecewise definition: expecting '=' after condition.");
+        test_parse_error("f(x) | = 1", "Invalid expression: unexpected end of input.");
+    }
+
+
     #[test]
     fn parse_commas_terminate() {
         test_parse("1+2,3", "+(1,2)");
     }
 
 
+    #[test]
+    fn parse_semicolons_terminate() {
+        let mut tokenizer = Tokenizer::new("1+2; 3").peekable();
+
+        assert_eq!(parse(&mut tokenizer, false, &Settings::new()).unwrap().to_string(), "+(1,2)");
+        assert!(matches!(tokenizer.next(), Some(Ok(Token::Text(";")))));
+
+        test_parse_error("(1;2)", "Invalid expression: expecting operator but got ';'.");
+    }
+
+
+    #[test]
+    fn parse_trailing_commas() {
+        test_parse("max(1,2,)", "max(1,2)");
+        test_parse("sin(1,)", "sin(1)");
+        test_parse("foo(1, bar(x,),)", "foo(1,bar(x()))");
+        test_parse("max(1, 2)", "max(1,2)");
+    }
+
+
     fn do_parse(expression: &str) -> Result<ExpressionNode, String> {
+        parse_with(expression, &Settings::new())
+    }
+
+
+    fn parse_with(expression: &str, settings: &Settings) -> Result<ExpressionNode, String> {
         let mut tokenizer = Tokenizer::new(expression).peekable();
-        parse(&mut tokenizer, false)
+        parse(&mut tokenizer, false, settings)
     }
     
     
@@ -488,6 +1343,7 @@ mod tests {
         test_parse_error("1 2", "Invalid expression: expecting operator but got '2'.");
         test_parse_error("e pi", "Invalid expression: expecting operator but got 'pi'.");
         test_parse_error("foo() bar()", "Invalid expression: expecting operator but got 'bar'.");
+        test_parse_error("1 + 'a\\tb'", "Invalid expression: unexpected string \"a\\tb\".");
 
         test_parse_error("e(1)", "Wrong number of arguments for e(): expected 0 but got 1.");
         test_parse_error("e(1,2,3)", "Wrong number of arguments for e(): expected 0 but got 3.");
@@ -496,6 +1352,13 @@ mod tests {
         test_parse_error("max()", "Wrong number of arguments for max(): expected 2 but got 0.");
         test_parse_error("max(1)", "Wrong number of arguments for max(): expected 2 but got 1.");
         test_parse_error("max(1,2,3)", "Wrong number of arguments for max(): expected 2 but got 3.");
+        test_parse_error("smoothstep(1,2)", "Wrong number of arguments for smoothstep(): expected 3 but got 2.");
+
+        test_parse_error("f(sin)", "Cannot pass function sin as a value.");
+        test_parse_error("max(1, cos)", "Cannot pass function cos as a value.");
+        test_parse_error("deriv(sqrt, 2)", "Cannot pass function sqrt as a value.");
+        test_parse_error("max(sin, 1)", "Cannot pass function sin as a value.");
+        test_parse_error("sin", "Wrong number of arguments for sin(): expected 1 but got 0.");
 
         test_parse_error("1ee2", "Invalid numeric constant '1ee2'.");
         test_parse_error("sin(1ee2)", "Invalid numeric constant '1ee2'.");
@@ -508,12 +1371,46 @@ mod tests {
         test_parse_error(")", "Invalid expression: too many close parentheses.");
         test_parse_error("x(y+z)/sqrt(10))+2", "Invalid expression: too many close parentheses.");
 
+        test_parse_error("max(1,,2)", "Empty argument in function call.");
+        test_parse_error("max(,2)", "Empty argument in function call.");
+        test_parse_error("max(,)", "Empty argument in function call.");
+        test_parse_error("foo(bar(1,,2))", "Empty argument in function call.");
+
         test_parse_error("x+", "Invalid expression: unexpected end of input.");
         test_parse_error("sqrt(", "Invalid expression: unexpected end of input.");
         test_parse_error("(1", "Invalid expression: unexpected end of input.");
     }
 
 
+    #[test]
+    fn parse_implicit_multiply() {
+        let mut settings = Settings::new();
+
+        assert_eq!(parse_with("2pi", &settings).unwrap_err(), "Invalid expression: expecting operator but got 'pi'.");
+        assert_eq!(parse_with("3(4+5)", &settings).unwrap_err(), "Invalid expression: unexpected open parenthesis.");
+
+        settings.implicit_multiply = true;
+
+        let test_implicit = |expression: &str, expected: &str| {
+            assert_eq!(format!("{}", parse_with(expression, &settings).unwrap()), expected);
+        };
+
+        test_implicit("2pi", "*(2,pi())");
+        test_implicit("2e", "*(2,e())");
+        test_implicit("2exp(1)", "*(2,exp(1))");
+        test_implicit("2e3", "2000");
+        test_implicit("3(4+5)", "*(3,+(4,5))");
+        test_implicit("2pi^2", "*(2,^(pi(),2))");
+        test_implicit("1/2pi", "*(/(1,2),pi())");
+        test_implicit("(1+2)(3+4)", "*(+(1,2),+(3,4))");
+        test_implicit("x sin(x)", "*(x(),sin(x()))");
+        test_implicit("max(2pi, 3)", "max(*(2,pi()),3)");
+        test_implicit("f(x) = 2x", "=(f(x()),*(2,x()))");
+
+        assert_eq!(parse_with("1 2", &settings).unwrap_err(), "Invalid expression: expecting operator but got '2'.");
+    }
+
+
     fn test_parse_error(expression: &str, expected_error: &str) {
         let error = do_parse(expression).unwrap_err();
         assert_eq!(error, expected_error);
@@ -538,6 +1435,38 @@ mod tests {
         assert_eq!(unwrap_eval("0 && 42"), 0.0);
         assert_eq!(unwrap_eval("23 && 0"), 0.0);
 
+        assert_eq!(unwrap_eval("0 ^| 0"), 0.0);
+        assert_eq!(unwrap_eval("0 ^| 1"), 1.0);
+        assert_eq!(unwrap_eval("1 ^| 0"), 1.0);
+        assert_eq!(unwrap_eval("1 ^| 1"), 0.0);
+        assert_eq!(unwrap_eval("1<2 ^| 3<4"), 0.0);
+        assert_eq!(unwrap_eval("1>2 ^| 3<4"), 1.0);
+
+        // Logical xor works on truthiness, unlike bitwise ^^.
+        assert_eq!(unwrap_eval("3 ^| 5"), 0.0);
+        assert_eq!(unwrap_eval("3 ^^ 5"), 6.0);
+        assert_eq!(unwrap_eval("-2 ^| 0"), 1.0);
+        assert_eq!(unwrap_eval("0.5 ^| 0"), 1.0);
+        assert_eq!(unwrap_eval("0.5 ^^ 0"), 0.0);
+
+        assert_eq!(unwrap_eval("1->0"), 0.0);
+        assert_eq!(unwrap_eval("0->1"), 1.0);
+        assert_eq!(unwrap_eval("0->0"), 1.0);
+        assert_eq!(unwrap_eval("1->1"), 1.0);
+        assert_eq!(unwrap_eval("(1->0)==0"), 1.0);
+        assert_eq!(unwrap_eval("(0->1)==1"), 1.0);
+        assert_eq!(unwrap_eval("(0->0)==1"), 1.0);
+        assert_eq!(unwrap_eval("(1->1)==1"), 1.0);
+
+        // Implication binds looser than comparisons, so 1->0==0 means 1->(0==0).
+        assert_eq!(unwrap_eval("1->0==0"), 1.0);
+        assert_eq!(unwrap_eval("1->0==1"), 0.0);
+        assert_eq!(unwrap_eval("5 -> 3"), 1.0);
+        assert_eq!(unwrap_eval("1 -> 1 -> 0"), 0.0);
+        assert_eq!(unwrap_eval("0 -> 1 -> 0"), 1.0);
+        assert_eq!(unwrap_eval("(0 -> 1) -> 0"), 0.0);
+        assert_eq!(unwrap_eval("3->-1"), 1.0);
+
         assert_eq!(unwrap_eval("!0"), 1.0);
         assert_eq!(unwrap_eval("!1"), 0.0);
         assert_eq!(unwrap_eval("!100"), 0.0);
@@ -548,6 +1477,31 @@ mod tests {
     }
 
 
+    #[test]
+    fn eval_nan_conditions() {
+        let mut context = Context::new();
+
+        assert_eq!(do_eval("(0/0) ? 1 : 2", &mut context).unwrap(), 2.0);
+        assert_eq!(do_eval("!(0/0)", &mut context).unwrap(), 1.0);
+        assert!(do_eval("(0/0) && 1", &mut context).unwrap().is_nan());
+        assert_eq!(do_eval("(0/0) || 5", &mut context).unwrap(), 5.0);
+        assert_eq!(do_eval("1 ^| 0/0", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("0/0 -> 0", &mut context).unwrap(), 1.0);
+
+        context.settings.strict = true;
+
+        assert_eq!(do_eval("(0/0) ? 1 : 2", &mut context).unwrap_err(), "NaN is not a valid condition.");
+        assert_eq!(do_eval("!(0/0)", &mut context).unwrap_err(), "NaN is not a valid condition.");
+        assert_eq!(do_eval("(0/0) && 1", &mut context).unwrap_err(), "NaN is not a valid condition.");
+        assert_eq!(do_eval("1 ^| 0/0", &mut context).unwrap_err(), "NaN is not a valid condition.");
+
+        // Values that are not used as conditions can still be NaN.
+        assert!(do_eval("1 ? 0/0 : 2", &mut context).unwrap().is_nan());
+        assert!(do_eval("0 || 0/0", &mut context).unwrap().is_nan());
+        assert_eq!(do_eval("(0/0) == 1", &mut context).unwrap(), 0.0);
+    }
+
+
     #[test]
     fn eval_bitwise() {
         assert_eq!(unwrap_eval("0x1234 | 0x5678"), 22140.0);
@@ -586,9 +1540,79 @@ mod tests {
         assert_eq!(unwrap_eval("0x80000000 >>> -1"), -1.0);
         assert_eq!(unwrap_eval("1234 >>> 2"), 308.0);
 
+        for x in [ "1", "123", "1234", "-5", "0x80000000", "2.5" ] {
+            for n in [ "0", "1", "4", "30", "31", "32", "-1", "1.5" ] {
+                assert_eq!(unwrap_eval(&format!("shl({}, {})", x, n)), unwrap_eval(&format!("{} << {}", x, n)));
+                assert_eq!(unwrap_eval(&format!("shr({}, {})", x, n)), unwrap_eval(&format!("{} >> {}", x, n)));
+                assert_eq!(unwrap_eval(&format!("sar({}, {})", x, n)), unwrap_eval(&format!("{} >>> {}", x, n)));
+            }
+        }
+
+        assert_eq!(unwrap_eval("shl(1, 4)"), 16.0);
+        assert_eq!(unwrap_eval("shr(0x80000000, 31)"), 1.0);
+        assert_eq!(unwrap_eval("sar(0x80000000, 31)"), -1.0);
+
         assert_eq!(unwrap_eval("~0"), -1.0);
         assert_eq!(unwrap_eval("~-1"), 0.0);
         assert_eq!(unwrap_eval("~1234"), -1235.0);
+
+        assert_eq!(unwrap_eval("nand(0xf0, 0x0f)"), -1.0);
+        assert_eq!(unwrap_eval("nand(0xff, 0x0f)"), -16.0);
+        assert_eq!(unwrap_eval("nor(0xf0, 0x0f)"), -256.0);
+        assert_eq!(unwrap_eval("nor(0, 0)"), -1.0);
+        assert_eq!(unwrap_eval("xnor(0xff, 0xff)"), -1.0);
+        assert_eq!(unwrap_eval("xnor(0xff, 0x0f)"), -241.0);
+        assert_eq!(unwrap_eval("u32(nand(-1, -1))"), 0.0);
+    }
+
+
+    #[test]
+    fn eval_bit_width_functions() {
+        let mut context = Context::new();
+
+        assert_eq!(do_eval("bitreverse(1)", &mut context).unwrap(), 2147483648.0);
+        assert_eq!(do_eval("bitreverse(0x80000000)", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("bitreverse(0x0f)", &mut context).unwrap(), 4026531840.0);
+        assert_eq!(do_eval("bitreverse(-1)", &mut context).unwrap(), 4294967295.0);
+        assert_eq!(do_eval("bitreverse(bitreverse(1234))", &mut context).unwrap(), 1234.0);
+
+        assert_eq!(do_eval("swapbytes(0x12345678)", &mut context).unwrap(), 0x78563412 as f64);
+        assert_eq!(do_eval("swapbytes(0xff)", &mut context).unwrap(), 0xff000000u32 as f64);
+        assert_eq!(do_eval("swapbytes(-2)", &mut context).unwrap(), 0xfeffffffu32 as f64);
+
+        assert_eq!(do_eval("setbit(0, 3)", &mut context).unwrap(), 8.0);
+        assert_eq!(do_eval("setbit(8, 3)", &mut context).unwrap(), 8.0);
+        assert_eq!(do_eval("setbit(0, 31)", &mut context).unwrap(), 2147483648.0);
+        assert_eq!(do_eval("setbit(0, 33)", &mut context).unwrap(), 2.0);
+        assert_eq!(do_eval("clearbit(0xff, 0)", &mut context).unwrap(), 254.0);
+        assert_eq!(do_eval("clearbit(0xfe, 0)", &mut context).unwrap(), 254.0);
+        assert_eq!(do_eval("clearbit(-1, 31)", &mut context).unwrap(), 2147483647.0);
+        assert_eq!(do_eval("getbit(0x80, 7)", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("getbit(0x80, 6)", &mut context).unwrap(), 0.0);
+        assert_eq!(do_eval("getbit(-1, 31)", &mut context).unwrap(), 1.0);
+
+        context.settings.integer_width = IntegerWidth::Fixed(8);
+
+        assert_eq!(do_eval("bitreverse(1)", &mut context).unwrap(), 128.0);
+        assert_eq!(do_eval("bitreverse(0x1c0)", &mut context).unwrap(), 3.0);
+        assert_eq!(do_eval("swapbytes(0x1234)", &mut context).unwrap(), 0x34 as f64);
+        assert_eq!(do_eval("setbit(0, 9)", &mut context).unwrap(), 2.0);
+        assert_eq!(do_eval("getbit(0x101, 8)", &mut context).unwrap(), 1.0);
+
+        context.settings.integer_width = IntegerWidth::Fixed(16);
+
+        assert_eq!(do_eval("swapbytes(0x1234)", &mut context).unwrap(), 0x3412 as f64);
+
+        context.settings.integer_width = IntegerWidth::Fixed(64);
+
+        assert_eq!(do_eval("bitreverse(2^11)", &mut context).unwrap(), 2f64.powi(52));
+
+        context.settings.integer_width = IntegerWidth::Auto;
+
+        assert_eq!(do_eval("bitreverse(1)", &mut context).unwrap(), 128.0);
+        assert_eq!(do_eval("bitreverse(0x100)", &mut context).unwrap(), 128.0);
+        assert_eq!(do_eval("swapbytes(0x123456)", &mut context).unwrap(), 0x56341200 as f64);
+        assert_eq!(do_eval("setbit(0, 40)", &mut context).unwrap(), 2f64.powi(40));
     }
 
 
@@ -617,6 +1641,49 @@ mod tests {
         assert_eq!(unwrap_eval("1 >= 2"), 0.0);
         assert_eq!(unwrap_eval("1 >= 1"), 1.0);
         assert_eq!(unwrap_eval("2 >= 1"), 1.0);
+
+        assert_eq!(unwrap_eval("3 <? 5"), 3.0);
+        assert_eq!(unwrap_eval("5 <? 3"), 3.0);
+        assert_eq!(unwrap_eval("3 >? 5"), 5.0);
+        assert_eq!(unwrap_eval("5 >? 3"), 5.0);
+        assert_eq!(unwrap_eval("3 <? 5 == 3"), 1.0);
+        assert_eq!(unwrap_eval("3 >? 5 == 5"), 1.0);
+        assert_eq!(unwrap_eval("-1 >? 2 <? 1.5"), 1.5);
+
+        assert_eq!(unwrap_eval("sqrt(2)^2 == 2"), 0.0);
+        assert_eq!(unwrap_eval("sqrt(2)^2 ~= 2"), 1.0);
+        assert_eq!(unwrap_eval("1 ~= 2"), 0.0);
+        assert_eq!(unwrap_eval("1 ~= 1"), 1.0);
+        assert_eq!(unwrap_eval("0.1 + 0.2 ~= 0.3"), 1.0);
+        assert_eq!(unwrap_eval("1e-12 ~= 0"), 1.0);
+        assert_eq!(unwrap_eval("1e20 ~= 1e20 + 1e10"), 1.0);
+        assert_eq!(unwrap_eval("1e20 ~= 1e20 + 1e12"), 0.0);
+        assert_eq!(unwrap_eval("1/0 ~= 1/0"), 1.0);
+        assert_eq!(unwrap_eval("1/0 ~= 1e308"), 0.0);
+        assert_eq!(unwrap_eval("0/0 ~= 0/0"), 0.0);
+        assert_eq!(unwrap_eval("1 ~= 1 == 1"), 1.0);
+    }
+
+
+    #[test]
+    fn eval_epsilon() {
+        let mut context = Context::new();
+
+        assert_eq!(context.settings.epsilon, 1e-9);
+
+        assert_eq!(do_eval("1.001 ~= 1", &mut context).unwrap(), 0.0);
+        assert_eq!(do_eval("1 + 1e-10 ~= 1", &mut context).unwrap(), 1.0);
+
+        context.settings.epsilon = 0.01;
+
+        assert_eq!(do_eval("1.001 ~= 1", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("1.1 ~= 1", &mut context).unwrap(), 0.0);
+        assert_eq!(do_eval("1000 ~= 1005", &mut context).unwrap(), 1.0);
+
+        context.settings.epsilon = 0.0;
+
+        assert_eq!(do_eval("1 + 1e-10 ~= 1", &mut context).unwrap(), 0.0);
+        assert_eq!(do_eval("1 ~= 1", &mut context).unwrap(), 1.0);
     }
 
 
@@ -647,11 +1714,110 @@ mod tests {
         assert_eq!(unwrap_eval("16.5 % 5.25"), 0.75);
         assert_eq!(unwrap_eval("16.5 % -5.25"), 0.75);
 
+        assert_eq!(unwrap_eval("50%"), 0.5);
+        assert_eq!(unwrap_eval("10% * 3"), 0.30000000000000004);
+        assert_eq!(unwrap_eval("200 * 50%"), 100.0);
+        assert_eq!(unwrap_eval("80 - 25%"), 79.75);
+        assert_eq!(unwrap_eval("10 % 3 + 5%"), 1.05);
+
         assert_eq!(unwrap_eval("2 ^ 3"), 8.0);
         assert_eq!(unwrap_eval("2 ^ 1"), 2.0);
         assert_eq!(unwrap_eval("2 ^ 0"), 1.0);
         assert_eq!(unwrap_eval("2 ^ -2"), 0.25);
         assert_eq!(unwrap_eval("256 ^ 0.25"), 4.0);
+        assert_eq!(unwrap_eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(unwrap_eval("2 ^ 10"), 1024.0);
+        assert_eq!(unwrap_eval("10 ^ 3"), 1000.0);
+        assert_eq!(unwrap_eval("-3 ^ 3"), -27.0);
+        assert_eq!(unwrap_eval("(-3) ^ 4"), 81.0);
+        assert_eq!(unwrap_eval("2 ^ 53"), 9007199254740992.0);
+        assert_eq!(unwrap_eval("3 ^ 33"), 5559060566555523.0);
+        assert_eq!(unwrap_eval("10 ^ 22"), 1e22);
+        assert_eq!(unwrap_eval("2 ^ 1024"), f64::INFINITY);
+        assert_eq!(unwrap_eval("0 ^ 0"), 1.0);
+        assert_eq!(unwrap_eval("2 ^ 0.5"), f64::consts::SQRT_2);
+        assert_eq!(unwrap_eval("1.5 ^ 2"), 2.25);
+        assert_eq!(unwrap_eval("10 ^ -3"), 0.001);
+    }
+
+
+    #[test]
+    fn eval_divide_by_zero() {
+        let mut context = Context::new();
+
+        assert!(do_eval("3 / 0", &mut context).unwrap().is_infinite());
+        assert!(do_eval("0 / 0", &mut context).unwrap().is_nan());
+        assert!(do_eval("3 % 0", &mut context).unwrap().is_nan());
+
+        context.settings.divide_by_zero_is_error = true;
+
+        assert_eq!(do_eval("3 / 0", &mut context).unwrap_err(), "Division by zero.");
+        assert_eq!(do_eval("0 / 0", &mut context).unwrap_err(), "Division by zero.");
+        assert_eq!(do_eval("3 % 0", &mut context).unwrap_err(), "Division by zero.");
+        assert_eq!(do_eval("1 + 3 / (2 - 2)", &mut context).unwrap_err(), "Division by zero.");
+        assert_eq!(do_eval("3 / 2", &mut context).unwrap(), 1.5);
+        assert_eq!(do_eval("3 * 0", &mut context).unwrap(), 0.0);
+    }
+
+
+    #[test]
+    fn eval_truncated_division() {
+        assert_eq!(unwrap_eval("idiv(7, 2)"), 3.0);
+        assert_eq!(unwrap_eval("imod(7, 2)"), 1.0);
+        assert_eq!(unwrap_eval("idiv(-7, 2)"), -3.0);
+        assert_eq!(unwrap_eval("imod(-7, 2)"), -1.0);
+        assert_eq!(unwrap_eval("idiv(7, -2)"), -3.0);
+        assert_eq!(unwrap_eval("imod(7, -2)"), 1.0);
+        assert_eq!(unwrap_eval("idiv(-7, -2)"), 3.0);
+        assert_eq!(unwrap_eval("imod(-7, -2)"), -1.0);
+        assert_eq!(unwrap_eval("idiv(6, 3)"), 2.0);
+        assert_eq!(unwrap_eval("imod(-6, 3)"), 0.0);
+
+        // Unlike %, which rounds down so its remainder takes the sign of the divisor.
+        assert_eq!(unwrap_eval("-7 % 2"), 1.0);
+
+        for (x, y) in [ (7, 2), (-7, 2), (7, -2), (-7, -2), (100, 7), (-100, 7), (3, 10), (-3, 10) ] {
+            assert_eq!(unwrap_eval(&format!("idiv({x}, {y}) * {y} + imod({x}, {y})", x = x, y = y)), x as f64);
+        }
+
+        // The quotient is derived from the remainder, so the two agree even when x / y rounds.
+        assert_eq!(unwrap_eval("idiv(1, 0.1)"), 9.0);
+        assert_eq!(unwrap_eval("imod(1, 0.1)"), 0.09999999999999995);
+        assert_eq!(unwrap_eval("idiv(-7.5, 2)"), -3.0);
+        assert_eq!(unwrap_eval("imod(-7.5, 2)"), -1.5);
+
+        for (x, y) in [ (1.0, 0.1), (-1.0, 0.1), (1.0, -0.1), (7.5, 2.0), (-7.5, 2.0), (7.5, -2.0), (5.3, 1.1), (-5.3, -1.1), (10.0, 0.3), (-2.5, 0.5) ] {
+            assert_eq!(unwrap_eval(&format!("idiv({x}, {y}) * {y} + imod({x}, {y})", x = x, y = y)), x, "x = {}, y = {}", x, y);
+        }
+
+        let mut context = Context::new();
+
+        assert!(do_eval("idiv(3, 0)", &mut context).unwrap().is_infinite());
+        assert!(do_eval("idiv(0, 0)", &mut context).unwrap().is_nan());
+        assert!(do_eval("imod(3, 0)", &mut context).unwrap().is_nan());
+
+        context.settings.divide_by_zero_is_error = true;
+
+        assert_eq!(do_eval("idiv(3, 0)", &mut context).unwrap_err(), "Division by zero.");
+        assert_eq!(do_eval("imod(3, 0)", &mut context).unwrap_err(), "Division by zero.");
+    }
+
+
+    #[test]
+    fn eval_integer_conversion() {
+        let mut context = Context::new();
+
+        assert_eq!(do_eval("1 << 2.9", &mut context).unwrap(), 4.0);
+        assert_eq!(do_eval("~-0.6", &mut context).unwrap(), -1.0);
+        assert_eq!(do_eval("u8(2.5)", &mut context).unwrap(), 2.0);
+        assert_eq!(do_eval("2.9 + 0.4", &mut context).unwrap(), 3.3);
+
+        context.settings.integer_conversion = IntegerConversion::Round;
+
+        assert_eq!(do_eval("1 << 2.9", &mut context).unwrap(), 8.0);
+        assert_eq!(do_eval("~-0.6", &mut context).unwrap(), 0.0);
+        assert_eq!(do_eval("u8(2.5)", &mut context).unwrap(), 3.0);
+        assert_eq!(do_eval("2.9 + 0.4", &mut context).unwrap(), 3.3);
     }
 
 
@@ -663,6 +1829,16 @@ mod tests {
         assert_eq!(unwrap_eval("min(1, 2)"), 1.0);
         assert_eq!(unwrap_eval("min(2, 1)"), 1.0);
 
+        assert_eq!(unwrap_eval("rem(10, 3)"), 1.0);
+        assert_eq!(unwrap_eval("rem(-16.5, 5.25)"), -0.75);
+        assert_eq!(unwrap_eval("rem(16.5, -5.25)"), 0.75);
+        assert_eq!(unwrap_eval("rem(-16.5, -5.25)"), -0.75);
+
+        assert_eq!(unwrap_eval("mod(10, 3)"), 1.0);
+        assert_eq!(unwrap_eval("mod(-16.5, 5.25)"), 4.5);
+        assert_eq!(unwrap_eval("mod(16.5, -5.25)"), 0.75);
+        assert_eq!(unwrap_eval("mod(-16.5, -5.25)"), 4.5);
+
         assert_eq!(unwrap_eval("sqrt(256)"), 16.0);
         assert_eq!(unwrap_eval("sqrt(100)"), 10.0);
         assert_eq!(unwrap_eval("sqrt(1)"), 1.0);
@@ -728,6 +1904,234 @@ mod tests {
     }
 
 
+    #[test]
+    fn eval_interpolation() {
+        assert_eq!(unwrap_eval("saturate(-0.5)"), 0.0);
+        assert_eq!(unwrap_eval("saturate(0.5)"), 0.5);
+        assert_eq!(unwrap_eval("saturate(1.5)"), 1.0);
+        assert_eq!(unwrap_eval("saturate(0)"), 0.0);
+        assert_eq!(unwrap_eval("saturate(1)"), 1.0);
+        assert_eq!(unwrap_eval("saturate(-1/0)"), 0.0);
+        assert_eq!(unwrap_eval("saturate(1/0)"), 1.0);
+        assert!(unwrap_eval("saturate(0/0)").is_nan());
+
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 0)"), 0.0);
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 1)"), 0.0);
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 2)"), 0.5);
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 1.5)"), 0.15625);
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 3)"), 1.0);
+        assert_eq!(unwrap_eval("smoothstep(1, 3, 4)"), 1.0);
+        assert_eq!(unwrap_eval("smoothstep(3, 1, 0)"), 1.0);
+        assert_eq!(unwrap_eval("smoothstep(3, 1, 4)"), 0.0);
+
+        assert_eq!(unwrap_eval("smoothstep(2, 2, 1.9)"), 0.0);
+        assert_eq!(unwrap_eval("smoothstep(2, 2, 2)"), 1.0);
+        assert_eq!(unwrap_eval("smoothstep(2, 2, 3)"), 1.0);
+
+        assert_eq!(unwrap_eval("wrap(370, 0, 360)"), 10.0);
+        assert_eq!(unwrap_eval("wrap(730, 0, 360)"), 10.0);
+        assert_eq!(unwrap_eval("wrap(-10, 0, 360)"), 350.0);
+        assert_eq!(unwrap_eval("wrap(-370, 0, 360)"), 350.0);
+        assert_eq!(unwrap_eval("wrap(90, 0, 360)"), 90.0);
+        assert_eq!(unwrap_eval("wrap(0, 0, 360)"), 0.0);
+        assert_eq!(unwrap_eval("wrap(360, 0, 360)"), 0.0);
+        assert_eq!(unwrap_eval("wrap(190, -180, 180)"), -170.0);
+        assert_eq!(unwrap_eval("wrap(-180, -180, 180)"), -180.0);
+        assert_eq!(unwrap_eval("wrap(2.5, 1, 2)"), 1.5);
+        assert_eq!(unwrap_eval("wrap(-1e-20, 0, 360)"), 0.0);
+
+        assert!(unwrap_eval("wrap(5, 360, 0)").is_nan());
+        assert!(unwrap_eval("wrap(5, 1, 1)").is_nan());
+        assert!(unwrap_eval("wrap(1/0, 0, 360)").is_nan());
+        assert!(unwrap_eval("wrap(0/0, 0, 360)").is_nan());
+    }
+
+
+    #[test]
+    fn eval_floating_point() {
+        assert!(unwrap_eval("nextafter(1, 2)") > 1.0);
+        assert_eq!(unwrap_eval("nextafter(1, 2)"), 1.0 + f64::EPSILON);
+        assert_eq!(unwrap_eval("nextafter(1, 0)"), 1.0 - f64::EPSILON / 2.0);
+        assert_eq!(unwrap_eval("nextafter(-1, -2)"), -1.0 - f64::EPSILON);
+        assert_eq!(unwrap_eval("nextafter(-1, 0)"), -1.0 + f64::EPSILON / 2.0);
+        assert_eq!(unwrap_eval("nextafter(1, 1)"), 1.0);
+        assert_eq!(unwrap_eval("nextafter(0, 1)"), f64::from_bits(1));
+        assert_eq!(unwrap_eval("nextafter(0, -1)"), -f64::from_bits(1));
+        assert_eq!(unwrap_eval("nextafter(nextafter(0, 1), -1)"), 0.0);
+        assert_eq!(unwrap_eval("nextafter(2^-1022, 0)"), f64::MIN_POSITIVE - f64::from_bits(1));
+        assert_eq!(unwrap_eval("nextafter(1/0, 0)"), f64::MAX);
+        assert!(unwrap_eval("nextafter(nextafter(1/0, 0), 1/0)").is_infinite());
+        assert!(unwrap_eval("nextafter(0/0, 1)").is_nan());
+        assert!(unwrap_eval("nextafter(1, 0/0)").is_nan());
+
+        assert_eq!(unwrap_eval("ulp(1)"), 2.0f64.powi(-52));
+        assert_eq!(unwrap_eval("ulp(-1)"), 2.0f64.powi(-52));
+        assert_eq!(unwrap_eval("ulp(2)"), 2.0f64.powi(-51));
+        assert_eq!(unwrap_eval("ulp(0)"), f64::from_bits(1));
+        assert_eq!(unwrap_eval("ulp(2^-1030)"), f64::from_bits(1));
+        assert_eq!(unwrap_eval("ulp(nextafter(1/0, 0))"), 2.0f64.powi(971));
+        assert!(unwrap_eval("ulp(1/0)").is_infinite());
+        assert!(unwrap_eval("ulp(0/0)").is_nan());
+    }
+
+
+    #[test]
+    fn approx_eq() {
+        assert!(ops::approx_eq(1.0, 1.0, 0.0));
+        assert!(ops::approx_eq(1.0, 1.0 + 1e-10, 1e-9));
+        assert!(!ops::approx_eq(1.0, 1.0 + 1e-8, 1e-9));
+
+        // Relative to the larger magnitude, so the order of arguments doesn't matter.
+        assert!(ops::approx_eq(1e20, 1e20 + 1e10, 1e-9));
+        assert!(ops::approx_eq(1e20 + 1e10, 1e20, 1e-9));
+        assert!(!ops::approx_eq(1e20, 1.01e20, 1e-9));
+
+        // Absolute near zero.
+        assert!(ops::approx_eq(0.0, 1e-12, 1e-9));
+        assert!(ops::approx_eq(-1e-12, 1e-12, 1e-9));
+        assert!(!ops::approx_eq(0.0, 1e-6, 1e-9));
+
+        assert!(ops::approx_eq(f64::INFINITY, f64::INFINITY, 1e-9));
+        assert!(!ops::approx_eq(f64::INFINITY, f64::NEG_INFINITY, 1e-9));
+        assert!(!ops::approx_eq(f64::INFINITY, f64::MAX, 1.0));
+        assert!(!ops::approx_eq(f64::NAN, f64::NAN, 1.0));
+        assert!(!ops::approx_eq(f64::NAN, 1.0, f64::INFINITY));
+    }
+
+
+    #[test]
+    fn eval_special_functions() {
+        fn assert_near(expression: &str, expected: f64) {
+            let value = unwrap_eval(expression);
+            assert!(ops::approx_eq(value, expected, 1e-12), "{} = {}, expected {}", expression, value, expected);
+        }
+
+        assert_near("gamma(5)", 24.0);
+        assert_near("gamma(1)", 1.0);
+        assert_near("gamma(2)", 1.0);
+        assert_near("gamma(0.5)", f64::consts::PI.sqrt());
+        assert_near("gamma(-0.5)", -2.0 * f64::consts::PI.sqrt());
+        assert_near("gamma(171)", 7.257415615307994e306);
+        assert!(unwrap_eval("gamma(0)").is_nan());
+        assert!(unwrap_eval("gamma(-3)").is_nan());
+        assert!(unwrap_eval("gamma(200)").is_infinite());
+
+        assert_near("lgamma(5)", 24.0f64.ln());
+        assert_near("lgamma(0.5)", f64::consts::PI.sqrt().ln());
+        assert_near("lgamma(-0.5)", (2.0 * f64::consts::PI.sqrt()).ln());
+        assert_near("lgamma(200)", 857.9336698258574);
+        assert!(unwrap_eval("lgamma(0)").is_infinite());
+        assert!(unwrap_eval("lgamma(-3)").is_infinite());
+
+        fn assert_within(expression: &str, expected: f64, tolerance: f64) {
+            let value = unwrap_eval(expression);
+            assert!(ops::approx_eq(value, expected, tolerance), "{} = {}, expected {}", expression, value, expected);
+        }
+
+        assert_eq!(unwrap_eval("erf(0)"), 0.0);
+        assert_within("erf(1)", 0.8427007929497149, 1e-7);
+        assert_within("erf(-1)", -0.8427007929497149, 1e-7);
+        assert_within("erf(0.5)", 0.5204998778130465, 1e-7);
+        assert_within("erf(3)", 0.9999779095030014, 1e-7);
+        assert_eq!(unwrap_eval("erf(-2)"), -unwrap_eval("erf(2)"));
+        assert_eq!(unwrap_eval("erf(100)"), 1.0);
+
+        assert_within("erfc(0)", 1.0, 1e-7);
+        assert_within("erfc(1)", 0.15729920705028513, 1e-7);
+        assert_within("erfc(-1)", 1.8427007929497148, 1e-7);
+        assert_within("erfc(5) / 1.5374597944280349e-12", 1.0, 1.2e-7);
+    }
+
+
+    #[test]
+    fn eval_integer_math() {
+        assert_eq!(unwrap_eval("isint(3)"), 1.0);
+        assert_eq!(unwrap_eval("isint(3.5)"), 0.0);
+        assert_eq!(unwrap_eval("isint(-2)"), 1.0);
+        assert_eq!(unwrap_eval("isint(-2.5)"), 0.0);
+        assert_eq!(unwrap_eval("isint(0)"), 1.0);
+        assert_eq!(unwrap_eval("isint(2^60)"), 1.0);
+        assert_eq!(unwrap_eval("isint(1/0)"), 0.0);
+        assert_eq!(unwrap_eval("isint(-1/0)"), 0.0);
+        assert_eq!(unwrap_eval("isint(0/0)"), 0.0);
+
+        assert_eq!(unwrap_eval("isbool(0)"), 1.0);
+        assert_eq!(unwrap_eval("isbool(1)"), 1.0);
+        assert_eq!(unwrap_eval("isbool(0.5)"), 0.0);
+        assert_eq!(unwrap_eval("isbool(2)"), 0.0);
+        assert_eq!(unwrap_eval("isbool(-1)"), 0.0);
+        assert_eq!(unwrap_eval("isbool(0/0)"), 0.0);
+        assert_eq!(unwrap_eval("isbool(3 > 2)"), 1.0);
+
+        assert_eq!(unwrap_eval("digitsum(1234)"), 10.0);
+        assert_eq!(unwrap_eval("digitsum(9999999)"), 63.0);
+        assert_eq!(unwrap_eval("digitsum(7)"), 7.0);
+        assert_eq!(unwrap_eval("digitsum(0)"), 0.0);
+        assert_eq!(unwrap_eval("digitsum(-1234)"), 10.0);
+        assert_eq!(unwrap_eval("digitsum(12.99)"), 3.0);
+
+        assert_eq!(unwrap_eval("isprime(97)"), 1.0);
+        assert_eq!(unwrap_eval("isprime(91)"), 0.0);
+        assert_eq!(unwrap_eval("isprime(2)"), 1.0);
+        assert_eq!(unwrap_eval("isprime(3)"), 1.0);
+        assert_eq!(unwrap_eval("isprime(4)"), 0.0);
+        assert_eq!(unwrap_eval("isprime(1)"), 0.0);
+        assert_eq!(unwrap_eval("isprime(0)"), 0.0);
+        assert_eq!(unwrap_eval("isprime(-7)"), 0.0);
+        assert_eq!(unwrap_eval("isprime(7.5)"), 1.0);
+        assert_eq!(unwrap_eval("isprime(2147483647)"), 1.0);
+        assert!(unwrap_eval("isprime(2^60)").is_nan());
+
+        assert_eq!(unwrap_eval("nextprime(13)"), 17.0);
+        assert_eq!(unwrap_eval("nextprime(14)"), 17.0);
+        assert_eq!(unwrap_eval("nextprime(1)"), 2.0);
+        assert_eq!(unwrap_eval("nextprime(-10)"), 2.0);
+        assert_eq!(unwrap_eval("nextprime(2)"), 3.0);
+        assert!(unwrap_eval("nextprime(2^60)").is_nan());
+
+        assert_eq!(unwrap_eval("fib(0)"), 0.0);
+        assert_eq!(unwrap_eval("fib(1)"), 1.0);
+        assert_eq!(unwrap_eval("fib(2)"), 1.0);
+        assert_eq!(unwrap_eval("fib(10)"), 55.0);
+        assert_eq!(unwrap_eval("fib(78)"), 8944394323791464.0);
+        assert!(unwrap_eval("fib(2000)").is_infinite());
+        assert!(unwrap_eval("fib(-1)").is_nan());
+
+        assert_eq!(unwrap_eval("choose(5, 2)"), 10.0);
+        assert_eq!(unwrap_eval("choose(5, 3)"), 10.0);
+        assert_eq!(unwrap_eval("choose(5, 0)"), 1.0);
+        assert_eq!(unwrap_eval("choose(5, 5)"), 1.0);
+        assert_eq!(unwrap_eval("choose(5, 6)"), 0.0);
+        assert_eq!(unwrap_eval("choose(5, -1)"), 0.0);
+        assert_eq!(unwrap_eval("choose(52, 5)"), 2598960.0);
+        assert!(unwrap_eval("choose(1e15, 5e14)").is_infinite());
+        assert!(unwrap_eval("choose(-5, 2)").is_nan());
+
+        assert_eq!(unwrap_eval("modpow(2, 10, 1000)"), 24.0);
+        assert_eq!(unwrap_eval("modpow(3, 0, 7)"), 1.0);
+        assert_eq!(unwrap_eval("modpow(3, 0, 1)"), 0.0);
+        assert_eq!(unwrap_eval("modpow(-2, 3, 5)"), 2.0);
+        assert_eq!(unwrap_eval("modpow(4, 13, 497)"), 445.0);
+        assert_eq!(unwrap_eval("modpow(2, 2^50, 1000000007)"), 252824185.0);
+        assert_eq!(unwrap_eval("modpow(123456789, 987654321, 2^53 - 1)"), 5174680945532888.0);
+        assert!(unwrap_eval("modpow(2, 10, 0)").is_nan());
+        assert!(unwrap_eval("modpow(2, 10, -7)").is_nan());
+        assert!(unwrap_eval("modpow(2, -1, 7)").is_nan());
+        assert!(unwrap_eval("modpow(2, 10, 1e300)").is_nan());
+
+        assert_eq!(unwrap_eval("modinv(3, 11)"), 4.0);
+        assert_eq!(unwrap_eval("modinv(10, 17)"), 12.0);
+        assert_eq!(unwrap_eval("modinv(-3, 11)"), 7.0);
+        assert_eq!(unwrap_eval("modinv(14, 11)"), 4.0);
+        assert_eq!(unwrap_eval("modinv(1, 1)"), 0.0);
+        assert_eq!(unwrap_eval("modinv(2^52 + 1, 2^53 - 1)"), 3002399751580331.0);
+        assert!(unwrap_eval("modinv(2, 4)").is_nan());
+        assert!(unwrap_eval("modinv(0, 7)").is_nan());
+        assert!(unwrap_eval("modinv(3, 0)").is_nan());
+        assert!(unwrap_eval("modinv(3, -11)").is_nan());
+    }
+
+
     #[test]
     fn eval_trig() {
         let value: f64 = 0.5;
@@ -745,6 +2149,33 @@ mod tests {
         assert_eq!(unwrap_eval("asinh(0.5)"), value.asinh());
         assert_eq!(unwrap_eval("acosh(1.5)"), value2.acosh());
         assert_eq!(unwrap_eval("atanh(0.5)"), value.atanh());
+        assert_eq!(unwrap_eval("atan2(0.5, 1.5)"), value.atan2(value2));
+        assert_eq!(unwrap_eval("atan2(0, -1)"), f64::consts::PI);
+    }
+
+
+    #[test]
+    fn eval_trig_degrees() {
+        let mut context = Context::new();
+
+        context.settings.angle_unit = AngleUnit::Degrees;
+
+        assert_eq!(do_eval("asin(1)", &mut context).unwrap(), 90.0);
+        assert_eq!(do_eval("acos(-1)", &mut context).unwrap(), 180.0);
+        assert_eq!(do_eval("atan(1)", &mut context).unwrap(), 45.0);
+        assert_eq!(do_eval("atan2(1, 1)", &mut context).unwrap(), 45.0);
+        assert_eq!(do_eval("atan2(0, -1)", &mut context).unwrap(), 180.0);
+        assert_eq!(do_eval("atan2(-1, 0)", &mut context).unwrap(), -90.0);
+
+        assert_eq!(do_eval("sin(90)", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("cos(180)", &mut context).unwrap(), -1.0);
+        assert_eq!(do_eval("sin(asin(1))", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("asin(sin(90))", &mut context).unwrap(), 90.0);
+        assert_eq!(do_eval("atan(tan(45))", &mut context).unwrap(), 45.0);
+
+        // Hyperbolic functions don't take angles, so are unaffected.
+        assert_eq!(do_eval("sinh(1)", &mut context).unwrap(), 1f64.sinh());
+        assert_eq!(do_eval("asinh(1)", &mut context).unwrap(), 1f64.asinh());
     }
 
 
@@ -840,6 +2271,81 @@ mod tests {
     }
 
 
+    #[test]
+    fn eval_saturating_casts() {
+        assert_eq!(unwrap_eval("sat_i8(300)"), 127.0);
+        assert_eq!(unwrap_eval("sat_i8(127)"), 127.0);
+        assert_eq!(unwrap_eval("sat_i8(-5)"), -5.0);
+        assert_eq!(unwrap_eval("sat_i8(-300)"), -128.0);
+        assert_eq!(unwrap_eval("sat_i8(12.9)"), 12.0);
+        assert_eq!(unwrap_eval("sat_i8(1/0)"), 127.0);
+        assert_eq!(unwrap_eval("sat_i8(0/0)"), 0.0);
+
+        assert_eq!(unwrap_eval("sat_u8(-5)"), 0.0);
+        assert_eq!(unwrap_eval("sat_u8(300)"), 255.0);
+        assert_eq!(unwrap_eval("sat_u8(200)"), 200.0);
+        assert_eq!(unwrap_eval("sat_u8(-1/0)"), 0.0);
+
+        assert_eq!(unwrap_eval("sat_i16(40000)"), 32767.0);
+        assert_eq!(unwrap_eval("sat_i16(-40000)"), -32768.0);
+        assert_eq!(unwrap_eval("sat_u16(70000)"), 65535.0);
+        assert_eq!(unwrap_eval("sat_u16(-1)"), 0.0);
+        assert_eq!(unwrap_eval("sat_i32(1e10)"), 2147483647.0);
+        assert_eq!(unwrap_eval("sat_i32(-1e10)"), -2147483648.0);
+        assert_eq!(unwrap_eval("sat_u32(1e10)"), 4294967295.0);
+        assert_eq!(unwrap_eval("sat_u32(-1)"), 0.0);
+
+        // The wrapping casts are unchanged.
+        assert_eq!(unwrap_eval("i8(300)"), 44.0);
+    }
+
+
+    #[test]
+    fn eval_integer_mode() {
+        fn eval_exact(expression: &str) -> Value {
+            evaluate_exact(&do_parse(expression).unwrap(), &mut Context::new()).unwrap()
+        }
+
+        // Without integer mode, 9007199254740993 rounds to the nearest f64.
+        assert_eq!(unwrap_eval("9007199254740993 + 2"), 9007199254740994.0);
+
+        assert_eq!(eval_exact("9007199254740993 + 2"), Value::Integer(9007199254740995));
+        assert_eq!(eval_exact("9007199254740993 - 9007199254740992"), Value::Integer(1));
+        assert_eq!(eval_exact("-9007199254740993 - 2"), Value::Integer(-9007199254740995));
+        assert_eq!(eval_exact("3037000499 * 3037000499"), Value::Integer(9223372030926249001));
+        assert_eq!(eval_exact("2^53 + 1"), Value::Integer(9007199254740993));
+        assert_eq!(eval_exact("9007199254740993 % 10"), Value::Integer(3));
+        assert_eq!(eval_exact("-7 % 3"), Value::Integer(2));
+        assert_eq!(eval_exact("(1 << 62) + 1"), Value::Integer(4611686018427387905));
+        assert_eq!(eval_exact("9007199254740993 >> 1"), Value::Integer(4503599627370496));
+        assert_eq!(eval_exact("-16 >> 2"), Value::Integer(-4));
+        assert_eq!(eval_exact("9007199254740993 & 0xff"), Value::Integer(1));
+        assert_eq!(eval_exact("(1 << 40) | 1"), Value::Integer(1099511627777));
+        assert_eq!(eval_exact("(1 << 40) ^^ (1 << 40)"), Value::Integer(0));
+
+        // Fractions and overflow fall back to f64.
+        assert_eq!(eval_exact("9007199254740993 + 0.5"), Value::Float(9007199254740992.5));
+        assert_eq!(eval_exact("9223372036854775807 + 1"), Value::Float(9223372036854775808.0));
+        assert_eq!(eval_exact("1 << 63"), Value::Float(-2147483648.0));
+        assert_eq!(eval_exact("7 / 2"), Value::Float(3.5));
+        assert_eq!(eval_exact("sqrt(16)"), Value::Float(4.0));
+        assert!(eval_exact("5 % 0").to_f64().is_nan());
+
+        let mut context = Context::new();
+        context.settings.divide_by_zero_is_error = true;
+        assert_eq!(evaluate_exact(&do_parse("5 % 0").unwrap(), &mut context).unwrap_err(), "Division by zero.");
+
+        // Fractional operands fall back to f64, converting to integers the same way as outside integer mode.
+        let mut context = Context::new();
+        assert_eq!(evaluate_exact(&do_parse("5.7 & 7").unwrap(), &mut context).unwrap(), Value::Float(5.0));
+
+        context.settings.integer_conversion = IntegerConversion::Round;
+        assert_eq!(do_eval("5.7 & 7", &mut context).unwrap(), 6.0);
+        assert_eq!(evaluate_exact(&do_parse("5.7 & 7").unwrap(), &mut context).unwrap(), Value::Float(6.0));
+        assert_eq!(evaluate_exact(&do_parse("1.5 << 2").unwrap(), &mut context).unwrap(), Value::Float(8.0));
+    }
+
+
     #[test]
     fn eval_special_constants() {
         assert_eq!(unwrap_eval("e"), f64::consts::E);
@@ -860,7 +2366,6 @@ mod tests {
 
     #[test]
     fn eval_invalid_operators() {
-        test_eval_error("a = b", "Invalid use of = operator.");
         test_eval_error("a ? b", "Invalid use of ? operator.");
         test_eval_error("a : b", "Invalid use of : operator.");
     }
@@ -892,6 +2397,151 @@ mod tests {
     }
 
 
+    #[test]
+    fn simplify() {
+        fn test_simplify(definition: &str, expected: &str) {
+            let (mut function, _) = test_deconstruct(definition).unwrap();
+            function.simplify();
+            assert_eq!(function.body(), expected);
+        }
+
+        test_simplify("f(x) = x + 0", "x()");
+        test_simplify("f(x) = 0 + x", "x()");
+        test_simplify("f(x) = x - 0", "x()");
+        test_simplify("f(x) = x * 1", "x()");
+        test_simplify("f(x) = 1 * x", "x()");
+        test_simplify("f(x) = x / 1", "x()");
+        test_simplify("f(x) = x ^ 1", "x()");
+        test_simplify("f(x) = x ^ 0", "1");
+        test_simplify("f(x) = 3 * 0", "0");
+        test_simplify("f(x) = 0 * 3", "0");
+
+        // Simplification works bottom up, so identities that appear after simplifying children are also removed.
+        test_simplify("f(x) = (x * 1 + 0) ^ (1 * 1)", "x()");
+        test_simplify("f(x) = sqrt(x + 0) * 2", "*(sqrt(x()),2)");
+
+        // These are not identities, or not safe to simplify.
+        test_simplify("f(x) = x * 0", "*(x(),0)");
+        test_simplify("f(x) = 0 * x", "*(0,x())");
+        test_simplify("f(x) = 0 - x", "-(0,x())");
+        test_simplify("f(x) = 1 / x", "/(1,x())");
+        test_simplify("f(x) = 1 ^ x", "^(1,x())");
+        test_simplify("f(x) = x % 1", "%(x(),1)");
+        test_simplify("f(x) = -x", "-(x())");
+        test_simplify("f(x) = x + 2", "+(x(),2)");
+    }
+
+
+    #[test]
+    fn variadic_arity() {
+        static SUM: ops::Operator = ops::Operator {
+            name: "sum",
+            precedence: ops::Precedence::None,
+            arity: 2,
+            min_arity: 2,
+            max_arity: 255,
+            is_right_associative: false,
+            function: OpFunction::Variadic(|args| args.iter().sum()),
+            flags: 0,
+            category: "",
+        };
+
+        assert!(!SUM.accepts_arity(1));
+        assert!(SUM.accepts_arity(2));
+        assert!(SUM.accepts_arity(255));
+        assert!(!SUM.accepts_arity(256));
+        assert_eq!(SUM.describe_arity(), "2 to 255");
+
+        let max = ops::find_function("max").unwrap();
+
+        assert!(max.accepts_arity(2));
+        assert!(!max.accepts_arity(3));
+        assert_eq!(max.describe_arity(), "2");
+
+        let mut context = Context::new();
+
+        for count in [ 2, 3, 255 ] {
+            let args = (1..=count).map(|i| ExpressionNode::Constant { value: i as f64 }).collect();
+            let expression = ExpressionNode::Operator { op: &SUM, args };
+
+            assert_eq!(evaluate(&expression, &mut context).unwrap(), (count * (count + 1) / 2) as f64);
+        }
+
+        let args = vec![ ExpressionNode::Constant { value: 1.0 }, ExpressionNode::Function { name: String::from("x"), args: vec![] } ];
+
+        assert_eq!(evaluate(&ExpressionNode::Operator { op: &SUM, args }, &mut context).unwrap_err(), "Unknown value x.");
+    }
+
+
+    #[test]
+    fn infix() {
+        fn test_infix(expression: &str, expected: &str) {
+            let infix = do_parse(expression).unwrap().to_infix();
+            assert_eq!(infix, expected);
+
+            // Parsing the infix text must give back the same tree.
+            assert_eq!(do_parse(&infix).unwrap().to_string(), do_parse(expression).unwrap().to_string());
+        }
+
+        test_infix("1+2*3", "(1 + (2 * 3))");
+        test_infix("(1+2)*3", "((1 + 2) * 3)");
+        test_infix("2^3^4", "(2 ^ (3 ^ 4))");
+        test_infix("-x + !y - ~z", "((-(x) + !(y)) - ~(z))");
+        test_infix("a ? b : c ? d : e", "(a ? b : (c ? d : e))");
+        test_infix("f(x, y+1) * g", "(f(x, (y + 1)) * g)");
+        test_infix("max(sin(x), pi)", "max(sin(x), pi)");
+        test_infix("50% * 3 % 2", "((((50)%) * 3) % 2)");
+        test_infix("x % -y", "(x % -(y))");
+        test_infix("(a = 5) + a", "((a = 5) + a)");
+        test_infix("deriv(sq, 0.25)", "deriv(sq, 0.25)");
+        test_infix("1e-10 + 1e20", "(0.0000000001 + 100000000000000000000)");
+
+        let (function, _) = test_deconstruct("f(x) | x<0 = -x | x>0 = x").unwrap();
+        assert_eq!(function.expression.to_infix(), "((x < 0) ? -(x) : ((x > 0) ? x : (0/0)))");
+
+        assert_eq!(ExpressionNode::Constant { value: -2.5 }.to_infix(), "(-2.5)");
+        assert_eq!(ExpressionNode::Constant { value: f64::INFINITY }.to_infix(), "(1/0)");
+        assert_eq!(ExpressionNode::Constant { value: f64::NEG_INFINITY }.to_infix(), "(-1/0)");
+    }
+
+
+    #[test]
+    fn rewrite() {
+        let mut expression = do_parse("1+2").unwrap();
+
+        expression.rewrite(|node| {
+            if let ExpressionNode::Constant{ value } = node {
+                *value += 1.0;
+            }
+        });
+
+        assert_eq!(expression.to_string(), "+(2,3)");
+
+        // Children are visited before their parents.
+        let mut expression = do_parse("f(1, -x) * 2").unwrap();
+        let mut visited = vec![];
+
+        expression.rewrite(|node| visited.push(node.to_string()));
+
+        assert_eq!(visited, vec![ "1", "x()", "-(x())", "f(1,-(x()))", "2", "*(f(1,-(x())),2)" ]);
+
+        // Parents see the already rewritten children, and can replace themselves entirely.
+        let mut expression = do_parse("sqrt(1+3) + 4").unwrap();
+
+        expression.rewrite(|node| {
+            if let ExpressionNode::Operator{ op, args } = node {
+                if let [ ExpressionNode::Constant{ value: x }, ExpressionNode::Constant{ value: y } ] = args.as_slice() {
+                    if *op == "+" {
+                        *node = ExpressionNode::Constant { value: x + y };
+                    }
+                }
+            }
+        });
+
+        assert_eq!(expression.to_string(), "+(sqrt(4),4)");
+    }
+
+
     #[test]
     fn variables() {
         let mut context = Context::new();
@@ -938,12 +2588,44 @@ mod tests {
         assert_eq!(do_eval("g(1)", &mut context).unwrap_err(), "Wrong number of arguments for g(): expected 2 but got 1.");
         assert_eq!(do_eval("g(1, 2, 3)", &mut context).unwrap_err(), "Wrong number of arguments for g(): expected 2 but got 3.");
 
+        assert_eq!(do_eval("f(g)", &mut context).unwrap_err(), "Cannot pass function g as a value.");
+        assert_eq!(do_eval("max(f, 1)", &mut context).unwrap_err(), "Cannot pass function f as a value.");
+        assert_eq!(do_eval("g(1, f) + 1", &mut context).unwrap_err(), "Cannot pass function f as a value.");
+        assert_eq!(do_eval("f(x)", &mut context).unwrap(), 1.0);
+        assert_eq!(do_eval("f(pi)", &mut context).unwrap(), 1.0);
+
         define_function("f(x) = x(1)", &mut context);
 
         assert_eq!(do_eval("f(1)", &mut context).unwrap_err(), "Use of x() as first class function is not supported.");
     }
 
 
+    #[test]
+    fn divergent_recursion() {
+        fn always_recurses(definition: &str) -> bool {
+            let mut expression = do_parse(definition).unwrap();
+            let (function, name) = deconstruct_function_definition(&mut expression).unwrap();
+            function.always_recurses(&name)
+        }
+
+        assert!(always_recurses("f(x) = f(x)"));
+        assert!(always_recurses("f(x) = 1 + f(x - 1)"));
+        assert!(always_recurses("f(x) = sin(f(x))"));
+        assert!(always_recurses("f(x) = g(f(x))"));
+        assert!(always_recurses("f(x) = x > 0 ? f(x - 1) : f(x + 1)"));
+        assert!(always_recurses("f(x) = f(x) || 1"));
+        assert!(always_recurses("x = x + 1"));
+
+        assert!(!always_recurses("factorial(n) = n>1 ? n * factorial(n-1) : 1"));
+        assert!(!always_recurses("f(x) = x > 0 ? f(x - 1) : 0"));
+        assert!(!always_recurses("f(x) = x && f(x - 1)"));
+        assert!(!always_recurses("f(x) | x>0 = f(x-1) | 1 = 0"));
+        assert!(!always_recurses("f(f) = f + 1"));
+        assert!(!always_recurses("f(x) = g(x)"));
+        assert!(!always_recurses("x = 2"));
+    }
+
+
     #[test]
     fn recursion() {
         let mut context = Context::new();
@@ -955,12 +2637,124 @@ mod tests {
         assert_eq!(do_eval("factorial(3)", &mut context).unwrap(), 6.0);
         assert_eq!(do_eval("factorial(10)", &mut context).unwrap(), 3628800.0);
 
-        assert_eq!(do_eval("factorial(1000)", &mut context).unwrap_err(), "Excessive recursion.");
+        assert_eq!(do_eval("factorial(1000)", &mut context).unwrap_err(), "Excessive recursion in factorial() at depth 256.");
+
+        context.settings.recursion_limit = 5;
+
+        assert_eq!(do_eval("factorial(5)", &mut context).unwrap(), 120.0);
+        assert_eq!(do_eval("factorial(6)", &mut context).unwrap_err(), "Excessive recursion in factorial() at depth 5.");
+
+        define_function("f(x) = g(x)", &mut context);
+        define_function("g(x) = f(x)", &mut context);
+
+        assert_eq!(do_eval("f(1)", &mut context).unwrap_err(), "Excessive recursion in g() at depth 5.");
+    }
+
+
+    #[test]
+    fn higher_order_functions() {
+        let mut context = Context::new();
+
+        define_function("sq(x) = x*x", &mut context);
+        define_function("add(x, y) = x + y", &mut context);
+
+        assert!((do_eval("deriv(sq, 3)", &mut context).unwrap() - 6.0).abs() < 1e-6);
+        assert!((do_eval("deriv(sq, -1 - 1)", &mut context).unwrap() + 4.0).abs() < 1e-6);
+
+        assert!((do_eval("integ(sq, 0, 3)", &mut context).unwrap() - 9.0).abs() < 1e-9);
+        assert!((do_eval("integ(sq, 0, 1) * 3", &mut context).unwrap() - 1.0).abs() < 1e-9);
+
+        define_function("g(x) = x*x - 2", &mut context);
+
+        assert!((do_eval("solve(g, 0, 2)", &mut context).unwrap() - 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(do_eval("solve(g, 2, 3)", &mut context).unwrap_err(), "solve() requires f(a) and f(b) to have opposite signs.");
+
+        define_function("slope(x) = deriv(sq, x)", &mut context);
+
+        assert!((do_eval("slope(5)", &mut context).unwrap() - 10.0).abs() < 1e-6);
+
+        assert_eq!(do_eval("deriv(sq)", &mut context).unwrap_err(), "Wrong number of arguments for deriv(): expected 2 but got 1.");
+        assert_eq!(do_eval("deriv(sq, 1, 2)", &mut context).unwrap_err(), "Wrong number of arguments for deriv(): expected 2 but got 3.");
+        assert_eq!(do_eval("deriv(2, 1)", &mut context).unwrap_err(), "First argument of deriv() must be the name of a function.");
+        assert_eq!(do_eval("deriv(sq(1), 1)", &mut context).unwrap_err(), "First argument of deriv() must be the name of a function.");
+        assert_eq!(do_eval("deriv(pi, 1)", &mut context).unwrap_err(), "First argument of deriv() must be the name of a function.");
+        assert_eq!(do_eval("deriv(bogus, 1)", &mut context).unwrap_err(), "Unknown function bogus.");
+        assert_eq!(do_eval("deriv(add, 1)", &mut context).unwrap_err(), "deriv() requires a function of one parameter, but add() has 2.");
+
+        assert_eq!(do_eval("integ(sq, 1)", &mut context).unwrap_err(), "Wrong number of arguments for integ(): expected 3 but got 2.");
+        assert_eq!(do_eval("integ(add, 0, 1)", &mut context).unwrap_err(), "integ() requires a function of one parameter, but add() has 2.");
+
+        define_function("f(sq) = deriv(sq, 1)", &mut context);
+
+        assert_eq!(do_eval("f(1)", &mut context).unwrap_err(), "First argument of deriv() must be the name of a function.");
+    }
+
+
+    #[test]
+    fn eval_with_bindings() {
+        let mut context = Context::new();
+        let expression = do_parse("x*x+1").unwrap();
+
+        assert_eq!(context.eval_with(&expression, &[ ("x", 3.0) ]).unwrap(), 10.0);
+        assert_eq!(context.eval_with(&expression, &[ ("x", -2.0) ]).unwrap(), 5.0);
+
+        assert_eq!(context.eval_with(&expression, &[]).unwrap_err(), "Unknown value x.");
+    }
+
+
+    #[test]
+    fn piecewise_functions() {
+        let mut context = Context::new();
+
+        define_function("f(x) | x<0 = -x | x>=0 = x", &mut context);
+        define_function("sign(x) | x<0 = -1 | x>0 = 1", &mut context);
+
+        assert_eq!(do_eval("f(-3)", &mut context).unwrap(), 3.0);
+        assert_eq!(do_eval("f(4)", &mut context).unwrap(), 4.0);
+        assert_eq!(do_eval("f(0)", &mut context).unwrap(), 0.0);
+
+        assert_eq!(do_eval("sign(-5)", &mut context).unwrap(), -1.0);
+        assert_eq!(do_eval("sign(5)", &mut context).unwrap(), 1.0);
+        assert!(do_eval("sign(0)", &mut context).unwrap().is_nan());
+    }
+
+
+    #[test]
+    fn nested_assignment() {
+        let mut context = Context::new();
+
+        assert_eq!(do_eval("(x = 5) + x", &mut context).unwrap(), 10.0);
+        assert_eq!(do_eval("x", &mut context).unwrap(), 5.0);
+
+        assert_eq!(do_eval("(y = x * 2) + (x = 1) + x", &mut context).unwrap(), 12.0);
+        assert_eq!(do_eval("y", &mut context).unwrap(), 10.0);
+        assert_eq!(do_eval("x", &mut context).unwrap(), 1.0);
+
+        assert_eq!(do_eval("a = b", &mut context).unwrap_err(), "Unknown value b.");
+        assert_eq!(do_eval("1 = 1", &mut context).unwrap_err(), "Assignment must be at the start of a definition (name = ... or f(args) = ...).");
+        assert_eq!(do_eval("2 + x = 3", &mut context).unwrap_err(), "Assignment must be at the start of a definition (name = ... or f(args) = ...).");
+        assert_eq!(do_eval("(2 + x = 3) * 2", &mut context).unwrap_err(), "Assignment must be at the start of a definition (name = ... or f(args) = ...).");
+        assert_eq!(do_eval("(f(y) = 1) + 1", &mut context).unwrap_err(), "Only simple variables can be assigned inside an expression.");
+        assert_eq!(do_eval("(ans = 1) + 1", &mut context).unwrap_err(), "Cannot define ans: that name is reserved.");
+        assert_eq!(do_eval("pi = 3", &mut context).unwrap_err(), "Cannot define pi: that name is reserved.");
+        assert_eq!(do_eval("sin(x) = 3", &mut context).unwrap_err(), "Cannot define sin: that name is reserved.");
+
+        define_function("f(n) = (n = 3) + n", &mut context);
+        define_function("g(n) = (z = n * 2) + 1", &mut context);
+
+        assert_eq!(do_eval("f(1)", &mut context).unwrap_err(), "Cannot assign to function parameter n.");
+        assert_eq!(do_eval("g(4)", &mut context).unwrap(), 9.0);
+        assert_eq!(do_eval("z", &mut context).unwrap(), 8.0);
+
+        define_function("h = (h = 2) + 1", &mut context);
+
+        assert_eq!(do_eval("h", &mut context).unwrap(), 3.0);
+        assert_eq!(do_eval("h", &mut context).unwrap(), 2.0);
     }
 
 
     fn define_function(expression: &str, context: &mut Context) {
         let (function, function_name) = test_deconstruct(expression).unwrap();
-        context.functions.insert(function_name, function);
+        context.set_function(function_name, function);
     }
 }
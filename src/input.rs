@@ -1,43 +1,83 @@
-use std::fs;
+use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, BufReader, IsTerminal, Lines, StdinLock, Write};
 
 
 pub struct InputSource {
-    text: Option<Vec<String>>
+    source: Source,
+
+    // Where to continue reading once the current source runs out, if anywhere.
+    then: Option<Source>,
+}
+
+
+// Where the input text comes from.
+enum Source {
+    // Reading from an interactive console, optionally showing a prompt before each line.
+    Console { show_prompt: bool },
+
+    // Commandline arguments, joined into a single line which is consumed on first read.
+    Args(Option<String>),
+
+    // Argument file, streamed one line at a time so huge files never need to fit in memory.
+    File(Lines<BufReader<File>>),
+
+    // Standard input named by a - argument, read like an argument file without any prompt.
+    Stdin(Lines<StdinLock<'static>>),
 }
 
 
 impl InputSource {
-    pub fn new(args: Vec<String>) -> InputSource {
-        if args.is_empty() {
-            // Reading from an interactive console.
-            InputSource { text: None }
+    // Interactive mode evaluates any arguments as a scripted prefix, then continues reading from the console.
+    pub fn new(args: Vec<String>, show_prompt: bool, interactive: bool) -> InputSource {
+        let then = if interactive && !args.is_empty() {
+            Some(Source::Console { show_prompt })
+        } else {
+            None
+        };
+
+        let source = if args.is_empty() {
+            Source::Console { show_prompt }
+        } else if is_stdin_arg(&args) {
+            Source::Stdin(io::stdin().lock().lines())
         } else {
             // Should we read an argument file, or use the commandline arguments directly?
-            let mut text = match read_arg_file(&args) {
-                Some(arg_file_contents) => arg_file_contents,
-                None => vec![ args.join(" ") ]
-            };
+            match open_arg_file(&args) {
+                Some(arg_file_lines) => Source::File(arg_file_lines),
+                None => Source::Args(Some(args.join(" ")))
+            }
+        };
 
-            text.reverse();
+        InputSource { source, then }
+    }
 
-            InputSource { text: Some(text) }
-        }
+
+    // Whether the most recent line was typed at an interactive console, as opposed to coming from
+    // arguments, an argument file, or a console whose input is redirected from somewhere else.
+    pub fn is_from_console(&self) -> bool {
+        matches!(self.source, Source::Console { .. }) && io::stdin().is_terminal()
     }
 }
 
 
-// If there is only one commandline argument, try to read that as an argument file.
-fn read_arg_file(args: &[String]) -> Option<Vec<String>> {
+// A lone - argument means read from stdin, so erik can be used at the end of a pipeline.
+fn is_stdin_arg(args: &[String]) -> bool {
+    args.len() == 1 && args[0] == "-"
+}
+
+
+// If there is only one commandline argument, try to open that as an argument file.
+fn open_arg_file(args: &[String]) -> Option<Lines<BufReader<File>>> {
     if args.len() == 1 {
         let filename = &args[0];
         
-        match fs::read_to_string(filename) {
-            Ok(file_contents) => Some(file_contents.lines()
-                                                   .map(String::from)
-                                                   .collect()),
-            Err(_) => None,
+        // Opening a directory can succeed, but it should be treated as a regular argument.
+        let file = File::open(filename).ok()?;
+
+        if file.metadata().ok()?.is_file() {
+            Some(BufReader::new(file).lines())
+        } else {
+            None
         }
     } else {
         None
@@ -45,31 +85,68 @@ fn read_arg_file(args: &[String]) -> Option<Vec<String>> {
 }
 
 
+// Argument files can contain blank lines and lines starting with #, which are skipped rather than evaluated.
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+
+    line.is_empty() || line.starts_with('#')
+}
+
+
 // Iterating over the input source yields a series of strings.
 impl Iterator for InputSource {
     type Item = String;
 
 
     fn next(&mut self) -> Option<String> {
-        match &mut self.text {
-            Some(text) => {
-                // Return text from commandline or argument file.
-                text.pop()
+        loop {
+            if let Some(line) = self.source.next_line() {
+                return Some(line);
+            }
+
+            self.source = self.then.take()?;
+        }
+    }
+}
+
+
+impl Source {
+    // Reads the next line, or None once this source is exhausted.
+    fn next_line(&mut self) -> Option<String> {
+        match self {
+            Source::Args(text) => {
+                // Return text from the commandline.
+                text.take()
             }
 
-            None => {
+            Source::File(lines) => {
+                // Return the next line of the argument file, stopping if it cannot be read.
+                lines.map_while(Result::ok)
+                     .find(|line| !is_blank_or_comment(line))
+            }
+
+            Source::Stdin(lines) => {
+                // Unlike the console, blank lines don't end piped input.
+                lines.next().and_then(|line| line.ok())
+            }
+
+            Source::Console { show_prompt } => {
                 // Read text from the console.
-                print!("\n> ");
+                let mut line = read_console_line("\n> ", *show_prompt)?;
 
-                if io::stdout().flush().is_err() {
-                    return None;
-                }
+                // Keep reading while there are unclosed parentheses, showing how many in the prompt.
+                // An empty line ends the continuation, leaving the parser to report the mismatch.
+                loop {
+                    let depth = open_paren_depth(&line);
 
-                let mut line = String::new();
+                    if depth == 0 {
+                        return Some(line);
+                    }
 
-                match io::stdin().read_line(&mut line) {
-                    Ok(_) if !line.trim().is_empty() => Some(line),
-                    _ => None
+                    match read_console_line(&format!("({})> ", depth), *show_prompt) {
+                        Some(continuation) => line += &continuation,
+                        None => return Some(line),
+                    }
                 }
             }
         }
@@ -77,14 +154,58 @@ impl Iterator for InputSource {
 }
 
 
+// Reads a line from the console, returning None at end of input or if the line is empty.
+fn read_console_line(prompt: &str, show_prompt: bool) -> Option<String> {
+    if show_prompt {
+        print!("{}", prompt);
+
+        if io::stdout().flush().is_err() {
+            return None;
+        }
+    }
+
+    let mut line = String::new();
+
+    match io::stdin().read_line(&mut line) {
+        Ok(_) if !line.trim().is_empty() => Some(line),
+        _ => None
+    }
+}
+
+
+// Counts how many parentheses are left open, ignoring any inside quoted strings.
+// Extra close parentheses are not counted against later open ones.
+fn open_paren_depth(text: &str) -> usize {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some(_), '\\')                 => { chars.next(); }
+            (Some(q), char) if char == q    => quote = None,
+            (Some(_), _)                    => {}
+            (None, '"') | (None, '\'')       => quote = Some(char),
+            (None, '(')                     => depth += 1,
+            (None, ')')                     => depth = depth.saturating_sub(1),
+            (None, _)                       => {}
+        }
+    }
+
+    depth
+}
+
+
 #[cfg(test)]
+#[allow(clippy::partialeq_to_none, clippy::assertions_on_constants, clippy::approx_constant)]
 mod tests {
     use super::*;
+    use std::fs;
 
 
     #[test]
     fn one_arg() {
-        let input = InputSource::new(vec![ String::from("Hello") ]);
+        let input = InputSource::new(vec![ String::from("Hello") ], true, false);
         let mut iter = input.into_iter();
 
         assert_eq!(iter.next().unwrap(), String::from("Hello"));
@@ -94,7 +215,7 @@ mod tests {
 
     #[test]
     fn two_args() {
-        let input = InputSource::new(vec![ String::from("Hello"), String::from("World") ]);
+        let input = InputSource::new(vec![ String::from("Hello"), String::from("World") ], true, false);
         let mut iter = input.into_iter();
 
         assert_eq!(iter.next().unwrap(), String::from("Hello World"));
@@ -106,7 +227,7 @@ mod tests {
     fn one_arg_file_exists() {
         fs::write("args.txt", "This\nis a\ntest").unwrap();
 
-        let input = InputSource::new(vec![ String::from("args.txt") ]);
+        let input = InputSource::new(vec![ String::from("args.txt") ], true, false);
         let mut iter = input.into_iter();
 
         assert_eq!(iter.next().unwrap(), String::from("This"));
@@ -118,11 +239,23 @@ mod tests {
     }
 
 
+    #[test]
+    fn arg_file_comments() {
+        fs::write("args4.txt", "# Helper functions\nsquare(x) = x * x\n\n   \n  # indented comment\nsquare(3)\n16#ff\n#").unwrap();
+
+        let lines: Vec<String> = InputSource::new(vec![ String::from("args4.txt") ], true, false).collect();
+
+        assert_eq!(lines, vec![ "square(x) = x * x", "square(3)", "16#ff" ]);
+
+        fs::remove_file("args4.txt").unwrap();
+    }
+
+
     #[test]
     fn two_args_file_exists() {
         fs::write("args2.txt", "This\nis a\ntest").unwrap();
 
-        let input = InputSource::new(vec![ String::from("args.txt"), String::from("another") ]);
+        let input = InputSource::new(vec![ String::from("args.txt"), String::from("another") ], true, false);
         let mut iter = input.into_iter();
 
         assert_eq!(iter.next().unwrap(), String::from("args.txt another"));
@@ -130,4 +263,87 @@ mod tests {
         
         fs::remove_file("args2.txt").unwrap();
     }
+
+
+    #[test]
+    fn stdin_arg() {
+        assert!(is_stdin_arg(&[ String::from("-") ]));
+        assert!(!is_stdin_arg(&[ String::from("-"), String::from("1") ]));
+        assert!(!is_stdin_arg(&[ String::from("-1") ]));
+        assert!(!is_stdin_arg(&[]));
+
+        assert!(matches!(InputSource::new(vec![ String::from("-") ], true, false).source, Source::Stdin(_)));
+        assert!(matches!(InputSource::new(vec![ String::from("-"), String::from("1") ], true, false).source, Source::Args(_)));
+    }
+
+
+    #[test]
+    fn interactive_mode() {
+        let input = InputSource::new(vec![ String::from("x=5") ], true, true);
+
+        assert!(matches!(input.source, Source::Args(_)));
+        assert!(matches!(input.then, Some(Source::Console { show_prompt: true })));
+
+        let input = InputSource::new(vec![ String::from("x=5") ], false, false);
+
+        assert!(matches!(input.source, Source::Args(_)));
+        assert!(input.then.is_none());
+
+        // With no arguments the console is read directly, whether or not interactive mode was requested.
+        let input = InputSource::new(vec![], true, true);
+
+        assert!(matches!(input.source, Source::Console { .. }));
+        assert!(input.then.is_none());
+    }
+
+
+    #[test]
+    fn lines_from_console() {
+        let mut input = InputSource::new(vec![ String::from("3+3") ], true, true);
+
+        assert_eq!(input.next().unwrap(), "3+3");
+        assert!(!input.is_from_console());
+
+        fs::write("args5.txt", "1\n2").unwrap();
+
+        let mut input = InputSource::new(vec![ String::from("args5.txt") ], true, false);
+
+        assert_eq!(input.next().unwrap(), "1");
+        assert!(!input.is_from_console());
+
+        fs::remove_file("args5.txt").unwrap();
+    }
+
+
+    #[test]
+    fn paren_depth() {
+        assert_eq!(open_paren_depth(""), 0);
+        assert_eq!(open_paren_depth("1 + 2"), 0);
+        assert_eq!(open_paren_depth("(1 + 2) * (3)"), 0);
+        assert_eq!(open_paren_depth("max(1, (2"), 2);
+        assert_eq!(open_paren_depth("max(1, (2)"), 1);
+        assert_eq!(open_paren_depth("(1 + 2))"), 0);
+        assert_eq!(open_paren_depth(") ("), 1);
+        assert_eq!(open_paren_depth("export '(' + ("), 1);
+        assert_eq!(open_paren_depth("basesep \"\\\"(\" ("), 1);
+        assert_eq!(open_paren_depth("'unclosed ("), 0);
+    }
+
+
+    #[test]
+    fn arg_file_is_streamed() {
+        fs::write("args3.txt", "1\n2\n").unwrap();
+
+        let input = InputSource::new(vec![ String::from("args3.txt") ], true, false);
+
+        // Nothing has been read yet, so lines appended after opening the file still show up.
+        let mut file = fs::OpenOptions::new().append(true).open("args3.txt").unwrap();
+        file.write_all(b"3\n4").unwrap();
+
+        let lines: Vec<String> = input.collect();
+
+        assert_eq!(lines, vec![ "1", "2", "3", "4" ]);
+
+        fs::remove_file("args3.txt").unwrap();
+    }
 }
@@ -0,0 +1,230 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+
+// Just enough JSON to read back what the export command writes: strings, arrays and objects.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+
+impl Value {
+    // Looks up a field of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|field| field.0 == key).map(|field| &field.1),
+            _ => None,
+        }
+    }
+}
+
+
+pub fn format_string(text: &str) -> String {
+    let mut result = String::from("\"");
+
+    for char in text.chars() {
+        match char {
+            '"'  => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            '\r' => result += "\\r",
+            '\t' => result += "\\t",
+            char if char.is_control() => result += &format!("\\u{:04x}", char as u32),
+            char => result.push(char),
+        }
+    }
+
+    result + "\""
+}
+
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+
+    let value = parse_value(&mut chars)?;
+
+    skip_whitespace(&mut chars);
+
+    match chars.next() {
+        None => Ok(value),
+        Some(char) => Err(format!("Unexpected '{}' after end of JSON.", char)),
+    }
+}
+
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+
+    match chars.next() {
+        Some('"') => Ok(Value::String(parse_string(chars)?)),
+        Some('[') => parse_array(chars),
+        Some('{') => parse_object(chars),
+        Some(char) => Err(format!("Unsupported JSON value starting with '{}'.", char)),
+        None => Err(String::from("Unexpected end of JSON.")),
+    }
+}
+
+
+// Parses the rest of a string, after the opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut result = String::new();
+
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(result),
+
+            Some('\\') => match chars.next() {
+                Some('"')  => result.push('"'),
+                Some('\\') => result.push('\\'),
+                Some('/')  => result.push('/'),
+                Some('n')  => result.push('\n'),
+                Some('r')  => result.push('\r'),
+                Some('t')  => result.push('\t'),
+                Some('b')  => result.push('\u{8}'),
+                Some('f')  => result.push('\u{c}'),
+
+                Some('u') => {
+                    let digits: String = chars.by_ref().take(4).collect();
+
+                    match u32::from_str_radix(&digits, 16).ok().and_then(std::char::from_u32) {
+                        Some(char) if digits.len() == 4 => result.push(char),
+                        _ => return Err(format!("Invalid JSON escape '\\u{}'.", digits)),
+                    }
+                }
+
+                Some(char) => return Err(format!("Invalid JSON escape '\\{}'.", char)),
+                None => return Err(String::from("Unterminated JSON string.")),
+            },
+
+            Some(char) => result.push(char),
+            None => return Err(String::from("Unterminated JSON string.")),
+        }
+    }
+}
+
+
+// Parses the rest of an array, after the opening bracket.
+fn parse_array(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    let mut items = vec![];
+
+    skip_whitespace(chars);
+
+    if chars.peek() == Some(&']') {
+        chars.next();
+        return Ok(Value::Array(items));
+    }
+
+    loop {
+        items.push(parse_value(chars)?);
+
+        if !parse_separator(chars, ']')? {
+            return Ok(Value::Array(items));
+        }
+    }
+}
+
+
+// Parses the rest of an object, after the opening brace.
+fn parse_object(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    let mut fields = vec![];
+
+    skip_whitespace(chars);
+
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return Ok(Value::Object(fields));
+    }
+
+    loop {
+        let key = match parse_value(chars)? {
+            Value::String(key) => key,
+            _ => return Err(String::from("JSON object keys must be strings.")),
+        };
+
+        skip_whitespace(chars);
+
+        if chars.next() != Some(':') {
+            return Err(format!("Expected ':' after JSON object key \"{}\".", key));
+        }
+
+        fields.push((key, parse_value(chars)?));
+
+        if !parse_separator(chars, '}')? {
+            return Ok(Value::Object(fields));
+        }
+    }
+}
+
+
+// Consumes a comma (returning true) or the closing delimiter (returning false).
+fn parse_separator(chars: &mut Peekable<Chars>, close: char) -> Result<bool, String> {
+    skip_whitespace(chars);
+
+    match chars.next() {
+        Some(',') => Ok(true),
+        Some(char) if char == close => Ok(false),
+        Some(char) => Err(format!("Expected ',' or '{}' but found '{}'.", close, char)),
+        None => Err(String::from("Unexpected end of JSON.")),
+    }
+}
+
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|char| char.is_whitespace()) {
+        chars.next();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn string(text: &str) -> Value {
+        Value::String(String::from(text))
+    }
+
+
+    #[test]
+    fn format() {
+        assert_eq!(format_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+
+    #[test]
+    fn values() {
+        assert_eq!(parse(" \"hello\" ").unwrap(), string("hello"));
+        assert_eq!(parse("\"a\\\"b\\\\c\\nd\\u0041\"").unwrap(), string("a\"b\\c\ndA"));
+        assert_eq!(parse("[]").unwrap(), Value::Array(vec![]));
+        assert_eq!(parse("[ \"a\", [\"b\"] ]").unwrap(), Value::Array(vec![ string("a"), Value::Array(vec![ string("b") ]) ]));
+        assert_eq!(parse("{}").unwrap(), Value::Object(vec![]));
+
+        let object = parse("{ \"x\": \"1\", \"y\": [] }").unwrap();
+
+        assert_eq!(object.get("x"), Some(&string("1")));
+        assert_eq!(object.get("y"), Some(&Value::Array(vec![])));
+        assert_eq!(object.get("z"), None);
+        assert_eq!(string("x").get("x"), None);
+
+        assert_eq!(parse(&format_string("a\"b\\c\nd\u{1}")).unwrap(), string("a\"b\\c\nd\u{1}"));
+    }
+
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("").unwrap_err(), "Unexpected end of JSON.");
+        assert_eq!(parse("12").unwrap_err(), "Unsupported JSON value starting with '1'.");
+        assert_eq!(parse("\"abc").unwrap_err(), "Unterminated JSON string.");
+        assert_eq!(parse("\"\\q\"").unwrap_err(), "Invalid JSON escape '\\q'.");
+        assert_eq!(parse("\"\\u12\"").unwrap_err(), "Invalid JSON escape '\\u12\"'.");
+        assert_eq!(parse("[\"a\" \"b\"]").unwrap_err(), "Expected ',' or ']' but found '\"'.");
+        assert_eq!(parse("[\"a\",").unwrap_err(), "Unexpected end of JSON.");
+        assert_eq!(parse("{ [] }").unwrap_err(), "JSON object keys must be strings.");
+        assert_eq!(parse("{ \"a\" \"b\" }").unwrap_err(), "Expected ':' after JSON object key \"a\".");
+        assert_eq!(parse("[] x").unwrap_err(), "Unexpected 'x' after end of JSON.");
+    }
+}
@@ -0,0 +1,2160 @@
+pub mod calculus;
+pub mod expr;
+pub mod ops;
+pub mod settings;
+pub mod tokens;
+
+mod json;
+mod units;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::iter::Peekable;
+use std::rc::Rc;
+
+use settings::Settings;
+use tokens::{Token, Tokenizer};
+
+#[macro_use]
+extern crate lazy_static;
+
+
+// Global context stores all state of the calculator.
+pub struct Context {
+
+    // User defined functions.
+    functions: HashMap<String, Rc<expr::Function>>,
+    
+    // Configuration options, such as what number base(s) to display output in.
+    settings: Settings,
+
+    // Recently evaluated results, most recent first, for recall via ans(n).
+    results: VecDeque<f64>,
+
+    // Cached results of memoized functions, keyed by the bit patterns of their arguments.
+    memo_caches: HashMap<String, HashMap<Vec<u64>, f64>>,
+
+    // Data points accumulated by the stat command.
+    stat_values: Vec<f64>,
+
+    // Most recent error from evaluating a line, so embedders can read it without scraping output.
+    last_error: Option<String>,
+
+    // Lines entered so far, oldest first, including any loaded from a history file.
+    history: Vec<String>,
+
+    // How many of the history lines are already in the history file, so saving only appends new ones.
+    saved_history: usize,
+
+    // How many times a user function body has been evaluated, so tests can check what memoization skips.
+    #[cfg(test)]
+    function_calls: usize,
+}
+
+
+// How many previous results are remembered.
+const MAX_RESULTS: usize = 100;
+
+
+// Variable that is automatically set to the result of each top level expression.
+const ANSWER_VARIABLE: &str = "Ans";
+
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            functions: HashMap::new(),
+            settings: Settings::new(),
+            results: VecDeque::new(),
+            memo_caches: HashMap::new(),
+            stat_values: vec![],
+            last_error: None,
+            history: vec![],
+            saved_history: 0,
+            #[cfg(test)]
+            function_calls: 0,
+        }
+    }
+
+
+    // Starts building a context with non-default settings.
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder { settings: Settings::new(), bases: None }
+    }
+
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+
+    // Reads what number base(s) output is displayed in.
+    pub fn bases(&self) -> &[u32] {
+        &self.settings.bases
+    }
+
+
+    // Changes what number base(s) to display output in.
+    pub fn set_bases(&mut self, bases: &[u32]) -> Result<(), String> {
+        self.settings.set_bases(bases)
+    }
+
+
+    // Lists the names of all user defined functions, in sorted order.
+    pub fn function_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(|name| name.as_str()).collect();
+
+        names.sort_unstable();
+
+        names
+    }
+
+
+    // Looks up a user defined function.
+    pub fn get_function(&self, name: &str) -> Option<&expr::Function> {
+        self.functions.get(name).map(Rc::as_ref)
+    }
+
+
+    // Defines or replaces a user function. Memoized results may depend on the old definition,
+    // whether directly or through another function that calls it, so every cache is cleared.
+    fn set_function(&mut self, name: String, function: expr::Function) {
+        self.functions.insert(name, Rc::new(function));
+
+        for cache in self.memo_caches.values_mut() {
+            cache.clear();
+        }
+    }
+
+
+    // Remembers a result so it can be recalled via ans(n), discarding the oldest once the history is full.
+    fn add_result(&mut self, value: f64) {
+        self.results.truncate(MAX_RESULTS - 1);
+        self.results.push_front(value);
+    }
+
+
+    // Unlike ans(n), the previous answer is also kept as an ordinary variable, so it shows up
+    // in ls and can be overwritten. Exact integer mode results keep all their digits. This is
+    // stored directly rather than through set_function, since clearing the memo caches after
+    // every result would stop memoization helping beyond a single line.
+    fn set_answer(&mut self, value: expr::Value) {
+        let expression = match value {
+            expr::Value::Integer(value) => expr::ExpressionNode::Integer { value },
+            expr::Value::Float(value) => expr::ExpressionNode::Constant { value },
+        };
+
+        self.functions.insert(String::from(ANSWER_VARIABLE), Rc::new(expr::Function { expression, args: vec![] }));
+    }
+
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+
+    // Records an entered line. Blank lines are not worth remembering. An entry continued over several
+    // console lines is joined with spaces, since the history file stores one entry per line.
+    pub fn add_history(&mut self, line: &str) {
+        let line = line.trim().lines().map(str::trim).collect::<Vec<&str>>().join(" ");
+
+        if !line.is_empty() {
+            self.history.push(line);
+        }
+    }
+
+
+    // Reads lines saved by a previous session. A missing file just means there is no history yet.
+    pub fn load_history(&mut self, filename: &str) -> Result<(), String> {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(format!("Can't read {}: {}", filename, error)),
+        };
+
+        let mut loaded: Vec<String> = contents.lines().map(String::from).collect();
+
+        self.saved_history = loaded.len();
+
+        loaded.append(&mut self.history);
+        self.history = loaded;
+
+        Ok(())
+    }
+
+
+    // Appends lines entered since the history was loaded or last saved, creating the file if needed.
+    pub fn save_history(&mut self, filename: &str) -> Result<(), String> {
+        let new_lines = &self.history[self.saved_history..];
+
+        if new_lines.is_empty() {
+            return Ok(());
+        }
+
+        let text: String = new_lines.iter().map(|line| format!("{}\n", line)).collect();
+
+        fs::OpenOptions::new().create(true).append(true).open(filename)
+                              .and_then(|mut file| file.write_all(text.as_bytes()))
+                              .map_err(|error| format!("Can't write {}: {}", filename, error))?;
+
+        self.saved_history = self.history.len();
+
+        Ok(())
+    }
+
+
+    // Runs each line of a script, stopping early if one quits. Each line reports the value of its last
+    // displayed expression, None if it only defined functions, ran commands, or ended in ;, or its error.
+    pub fn run(&mut self, script: &str) -> Vec<Result<Option<f64>, String>> {
+        let mut outcomes = vec![];
+
+        for line in script.lines() {
+            let mut last_value = None;
+
+            match execute_line(line, self, &mut |value, _| last_value = Some(value.to_f64())) {
+                Ok(keep_going) => {
+                    outcomes.push(Ok(last_value));
+
+                    if !keep_going {
+                        break;
+                    }
+                }
+
+                Err(message) => outcomes.push(Err(message)),
+            }
+        }
+
+        outcomes
+    }
+
+
+    // Reads the most recent error from evaluate_line or run. Later successes don't clear it.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+
+    // Evaluates an expression with the specified values bound to local names.
+    pub fn eval_with(&mut self, expression: &expr::ExpressionNode, bindings: &[(&str, f64)]) -> Result<f64, String> {
+        expr::evaluate_with(expression, self, bindings)
+    }
+}
+
+
+impl Default for Context {
+    fn default() -> Context {
+        Context::new()
+    }
+}
+
+
+// Lets programs that embed the calculator configure it without going through the set command.
+pub struct ContextBuilder {
+    settings: Settings,
+
+    // Bases are validated when the context is built, so errors surface from a single place.
+    bases: Option<Vec<u32>>,
+}
+
+
+impl ContextBuilder {
+    pub fn base(self, base: u32) -> ContextBuilder {
+        self.bases(&[ base ])
+    }
+
+
+    pub fn bases(mut self, bases: &[u32]) -> ContextBuilder {
+        self.bases = Some(bases.to_vec());
+        self
+    }
+
+
+    pub fn precision(mut self, precision: usize) -> ContextBuilder {
+        self.settings.precision = Some(precision);
+        self
+    }
+
+
+    pub fn recursion_limit(mut self, recursion_limit: u32) -> ContextBuilder {
+        self.settings.recursion_limit = recursion_limit;
+        self
+    }
+
+
+    pub fn build(mut self) -> Result<Context, String> {
+        if let Some(bases) = self.bases {
+            self.settings.set_bases(&bases)?;
+        }
+
+        let mut context = Context::new();
+
+        context.settings = self.settings;
+
+        Ok(context)
+    }
+}
+
+
+pub fn evaluate_line(line: &str, context: &mut Context) -> Result<bool, String> {
+    execute_line(line, context, &mut |value, context| println!("{}", format_result(line, value, &context.settings)))
+}
+
+
+// Runs a line, passing the result of each expression to a callback as it is evaluated.
+// Returns false if the line was a command asking to quit. Errors are also kept for last_error.
+fn execute_line(line: &str, context: &mut Context, on_value: &mut dyn FnMut(expr::Value, &Context)) -> Result<bool, String> {
+    let result = execute_expressions(line, context, on_value);
+
+    if let Err(message) = &result {
+        context.last_error = Some(message.clone());
+    }
+
+    result
+}
+
+
+fn execute_expressions(line: &str, context: &mut Context, on_value: &mut dyn FnMut(expr::Value, &Context)) -> Result<bool, String> {
+    let mut tokenizer = Tokenizer::new(line).peekable();
+
+    // Is this a special command?
+    if let Some(result) = dispatch_command(&mut tokenizer, context) {
+        return result;
+    }
+    
+    while tokenizer.peek().is_some() {
+        let mut expression = expr::parse(&mut tokenizer, false, &context.settings)?;
+
+        // A trailing semicolon evaluates the expression without showing its result, as in x = 5; f(x);
+        let is_quiet = matches!(tokenizer.peek(), Some(Ok(Token::Text(";"))));
+
+        if is_quiet {
+            tokenizer.next();
+        }
+
+        if let Some((function, function_name)) = expr::deconstruct_function_definition(&mut expression) {
+            define_function(function, function_name, context)?;
+        } else {
+            // Evaluate an expression.
+            let value = if context.settings.integer_mode {
+                expr::evaluate_exact(&expression, context)?
+            } else {
+                expr::Value::Float(expr::evaluate(&expression, context)?)
+            };
+
+            context.add_result(value.to_f64());
+            context.set_answer(value);
+
+            if !is_quiet {
+                on_value(value, context);
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+
+fn define_function(mut function: expr::Function, function_name: String, context: &mut Context) -> Result<(), String> {
+    expr::check_function_name(&function_name)?;
+
+    function.simplify();
+
+    if function.always_recurses(&function_name) {
+        println!("Warning: {}() always calls itself, so it can never return a value.", function_name);
+    }
+
+    context.set_function(function_name, function);
+
+    Ok(())
+}
+
+
+// Evaluates each line of a file, reporting errors without stopping.
+pub fn evaluate_file(filename: &str, context: &mut Context) -> Result<(), String> {
+    let file_contents = fs::read_to_string(filename).map_err(|error| format!("Can't read {}: {}", filename, error))?;
+
+    for (line_number, line) in file_contents.lines().enumerate() {
+        match evaluate_line(line, context) {
+            Ok(true)     => {},
+            Ok(false)    => break,
+            Err(message) => println!("{}({}): {}", filename, line_number + 1, message),
+        }
+    }
+
+    Ok(())
+}
+
+
+fn dispatch_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Option<Result<bool, String>> {
+    // Check if the next input token is in the COMMANDS table, and dispatch through that if found.
+    if let Some(Ok(Token::Text(command))) = tokenizer.peek() {
+        if let Some(command) = COMMANDS.get(command) {
+            tokenizer.next();
+            
+            return Some(command(tokenizer, context));
+        }
+    }
+
+    None
+}
+
+
+// Special commands return a bool indicating whether to keep going, or an error if they could not run.
+type Command = Box<fn(&mut Peekable<Tokenizer>, &mut Context) -> Result<bool, String>>;
+
+
+lazy_static! {
+    static ref COMMANDS: HashMap<&'static str, Command> = [
+        ( "q",        Command::new(quit_command)     ),
+        ( "quit",     Command::new(quit_command)     ),
+        ( "exit",     Command::new(quit_command)     ),
+        ( "ls",       Command::new(ls_command)       ),
+        ( "help",     Command::new(help_command)     ),
+        ( "base",     Command::new(base_command)     ),
+        ( "tokens",   Command::new(tokens_command)   ),
+        ( "divzero",  Command::new(divzero_command)  ),
+        ( "set",      Command::new(set_command)      ),
+        ( "settings", Command::new(settings_command) ),
+        ( "precof",   Command::new(precof_command)   ),
+        ( "epsilon",  Command::new(epsilon_command)  ),
+        ( "export",   Command::new(export_command)   ),
+        ( "import",   Command::new(import_command)   ),
+        ( "freeze",   Command::new(freeze_command)   ),
+        ( "inspect",  Command::new(inspect_command)  ),
+        ( "char",     Command::new(char_command)     ),
+        ( "basesep",  Command::new(basesep_command)  ),
+        ( "inbase",   Command::new(inbase_command)   ),
+        ( "memoize",  Command::new(memoize_command)  ),
+        ( "timeout",  Command::new(timeout_command)  ),
+        ( "factor",   Command::new(factor_command)   ),
+        ( "gcd",      Command::new(gcd_command)      ),
+        ( "lcm",      Command::new(lcm_command)      ),
+        ( "convert",  Command::new(convert_command)  ),
+        ( "stat",     Command::new(stat_command)     ),
+        ( "outrange", Command::new(outrange_command) ),
+        ( "echo",     Command::new(echo_command)     ),
+        ( "intmode",  Command::new(intmode_command)  ),
+    ].iter().cloned().collect();
+}
+
+
+fn quit_command(_: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    Ok(false)
+}
+
+
+fn ls_command(_: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    use itertools::Itertools;
+
+    let sorted_functions = context.functions.iter().sorted_by_key(|f| f.0);
+    
+    for (name, function) in sorted_functions {
+        let expr::Function{ expression, args } = function.as_ref();
+
+        let args = if args.is_empty() {
+            String::from("")
+        } else {
+            String::from("(") + &args.join(",") + ")"
+        };
+        
+        println!("{}{} = {}", name, args, expression);
+    }
+    
+    Ok(true)
+}
+
+
+fn export_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    let json = format_export(context);
+
+    match tokenizer.next() {
+        None => print!("{}", json),
+
+        Some(Ok(Token::Str(filename))) if tokenizer.peek().is_none() => {
+            fs::write(&filename, json).map_err(|error| format!("Can't write {}: {}", filename, error))?;
+        }
+
+        _ => return Err(String::from("Usage: export ['filename']")),
+    }
+
+    Ok(true)
+}
+
+
+// Formats all user defined functions as a JSON array of { name, args, body } objects.
+// Bodies are written as infix expressions, so they can be read back in by the parser.
+fn format_export(context: &Context) -> String {
+    let entries: Vec<String> = context.function_names().iter().map(|name| {
+        let function = context.get_function(name).unwrap();
+
+        let args: Vec<String> = function.args.iter().map(|arg| json::format_string(arg)).collect();
+
+        format!("  {{ \"name\": {}, \"args\": [{}], \"body\": {} }}",
+                json::format_string(name),
+                args.join(", "),
+                json::format_string(&function.expression.to_infix()))
+    }).collect();
+
+    if entries.is_empty() {
+        String::from("[]\n")
+    } else {
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
+
+
+fn import_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    match (tokenizer.next(), tokenizer.peek()) {
+        (Some(Ok(Token::Str(filename))), None) => {
+            let errors = fs::read_to_string(&filename).map_err(|error| format!("Can't read {}: {}", filename, error))
+                                                     .and_then(|json| import_functions(&json, context))?;
+
+            for error in errors {
+                println!("{}", error);
+            }
+        }
+
+        _ => return Err(String::from("Usage: import 'filename'")),
+    }
+
+    Ok(true)
+}
+
+
+// Defines functions from JSON in the format written by export. Returns an error if the JSON as a whole
+// is malformed, otherwise a list of errors for individual entries that could not be imported.
+fn import_functions(json: &str, context: &mut Context) -> Result<Vec<String>, String> {
+    let entries = match json::parse(json)? {
+        json::Value::Array(entries) => entries,
+        _ => return Err(String::from("Expected a JSON array of functions.")),
+    };
+
+    let mut errors = vec![];
+
+    for (index, entry) in entries.iter().enumerate() {
+        let result = format_import_definition(entry).and_then(|definition| {
+            let mut tokenizer = Tokenizer::new(&definition).peekable();
+            let mut expression = expr::parse(&mut tokenizer, false, &context.settings)?;
+
+            match expr::deconstruct_function_definition(&mut expression) {
+                Some((function, function_name)) if tokenizer.peek().is_none() => define_function(function, function_name, context),
+
+                _ => Err(format!("'{}' is not a function definition.", definition)),
+            }
+        });
+
+        if let Err(message) = result {
+            errors.push(format!("Entry {}: {}", index + 1, message));
+        }
+    }
+
+    Ok(errors)
+}
+
+
+// Reconstructs the "name(args) = body" source text of an exported function.
+fn format_import_definition(entry: &json::Value) -> Result<String, String> {
+    let field = |key| match entry.get(key) {
+        Some(json::Value::String(value)) => Ok(value.as_str()),
+        _ => Err(format!("Expected a string \"{}\" field.", key)),
+    };
+
+    let name = field("name")?;
+    let body = field("body")?;
+
+    let args = match entry.get("args") {
+        None => vec![],
+
+        Some(json::Value::Array(args)) => args.iter().map(|arg| match arg {
+            json::Value::String(arg) => Ok(arg.as_str()),
+            _ => Err(String::from("Function arguments must be strings.")),
+        }).collect::<Result<Vec<&str>, String>>()?,
+
+        Some(_) => return Err(String::from("Expected an array \"args\" field.")),
+    };
+
+    if args.is_empty() {
+        Ok(format!("{} = {}", name, body))
+    } else {
+        Ok(format!("{}({}) = {}", name, args.join(", "), body))
+    }
+}
+
+
+fn freeze_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    match (tokenizer.next(), tokenizer.peek()) {
+        (Some(Ok(Token::Text(name))), None) => {
+            let value = freeze_function(name, context)?;
+
+            println!("{} = {}", name, format_number(value, &context.settings));
+        }
+
+        _ => return Err(String::from("Usage: freeze <name>")),
+    }
+
+    Ok(true)
+}
+
+
+// Replaces a function that takes no parameters with its current value, so it no
+// longer changes when the functions it refers to are redefined.
+fn freeze_function(name: &str, context: &mut Context) -> Result<f64, String> {
+    let function = context.functions.get(name).cloned().ok_or_else(|| format!("Unknown function {}.", name))?;
+
+    if !function.args.is_empty() {
+        return Err(format!("Can't freeze {}(): only functions without parameters can be frozen.", name));
+    }
+
+    let value = expr::evaluate(&function.expression, context)?;
+
+    context.set_function(String::from(name), expr::Function {
+        expression: expr::ExpressionNode::Constant { value },
+        args: vec![],
+    });
+
+    Ok(value)
+}
+
+
+fn help_command(_: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    print!("{}", format_help());
+
+    Ok(true)
+}
+
+
+fn format_help() -> String {
+    let mut result = String::new();
+
+    // Internal operators such as the ternary and terminator are created by the parser, so are never listed.
+    let operators = ops::OPERATORS.iter().filter(|op| !ops::is_internal(op)).map(|op| op.name)
+                                 .chain(expr::KEYWORDS.iter().map(|keyword| keyword.0))
+                                 .collect();
+
+    result += &format_help_section("Operators", operators);
+
+    for (category, functions) in function_categories() {
+        result += &format_help_section(category, functions);
+    }
+
+    result += &format_help_section("Calculus (first argument is the name of a function)", expr::HIGHER_ORDER_FUNCTIONS.iter().map(|function| function.name).collect());
+
+    result += &format_help_section("Commands", COMMANDS.iter().map(|cmd| *cmd.0).collect());
+
+    result
+}
+
+
+// Groups the builtin functions by category, in the order they appear in the table.
+fn function_categories() -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut categories: Vec<(&str, Vec<&str>)> = vec![];
+
+    for function in ops::FUNCTIONS.iter() {
+        match categories.iter_mut().find(|category| category.0 == function.category) {
+            Some(category) => category.1.push(function.name),
+            None => categories.push((function.category, vec![ function.name ])),
+        }
+    }
+
+    categories
+}
+
+
+fn format_help_section(title: &str, mut items: Vec<&str>) -> String {
+    let mut result = format!("\n{}:\n", title);
+
+    items.sort_unstable();
+
+    let item_width = items.iter().fold(0, |a, i| std::cmp::max(a, i.len())) + 2;
+
+    for line in items.chunks(60 / item_width) {
+        result += "    ";
+
+        for item in line {        
+            result += &format!("{:w$}", item, w = item_width);
+        }
+        
+        result += "\n";
+    }
+
+    result
+}
+
+
+// A thin wrapper over set divzero, which also accepts error and inf since those read better here.
+fn divzero_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    let value = match tokenizer.peek() {
+        Some(Ok(Token::Text("error"))) => "on",
+        Some(Ok(Token::Text("inf")))   => "off",
+        _ => return setting_command("divzero", tokenizer, context, describe_divzero),
+    };
+
+    tokenizer.next();
+
+    if tokenizer.peek().is_some() {
+        return Err(String::from("Usage: divzero <error|inf|on|off>"));
+    }
+
+    setting_command("divzero", &mut Tokenizer::new(value).peekable(), context, describe_divzero)
+}
+
+
+fn describe_divzero(settings: &Settings) -> String {
+    if settings.divide_by_zero_is_error {
+        String::from("Division by zero is an error")
+    } else {
+        String::from("Division by zero returns infinity")
+    }
+}
+
+
+fn precof_command(tokenizer: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    match tokenizer.next() {
+        Some(Ok(Token::Operator(op))) => println!("{}", describe_precedence(op)),
+        _ => return Err(String::from("Usage: precof <operator>")),
+    }
+
+    Ok(true)
+}
+
+
+fn describe_precedence(op: ops::OperatorRef) -> String {
+    let associativity = match (op.arity, op.is_right_associative) {
+        (1, _)     => "unary",
+        (_, true)  => "right associative",
+        (_, false) => "left associative",
+    };
+
+    format!("{} has {:?} precedence (level {}), {}", op.name, op.precedence, op.precedence as u32, associativity)
+}
+
+
+fn memoize_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    match (tokenizer.next(), tokenizer.peek()) {
+        (Some(Ok(Token::Text(name))), None) => {
+            memoize_function(name, context)?;
+
+            println!("Memoizing {}()", name);
+        }
+
+        _ => return Err(String::from("Usage: memoize <name>")),
+    }
+
+    Ok(true)
+}
+
+
+// Caches the results of a function, so repeated calls with the same arguments are only evaluated once.
+// This assumes the function is pure: changes to ans() or settings do not invalidate the cache.
+fn memoize_function(name: &str, context: &mut Context) -> Result<(), String> {
+    if !context.functions.contains_key(name) {
+        return Err(format!("Unknown function {}.", name));
+    }
+
+    context.memo_caches.entry(String::from(name)).or_default();
+
+    Ok(())
+}
+
+
+fn inspect_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    if tokenizer.peek().is_none() {
+        return Err(String::from("Usage: inspect <expression>"));
+    }
+
+    let expression = expr::parse(tokenizer, false, &context.settings)?;
+    let value = expr::evaluate(&expression, context)?;
+
+    print!("{}", format_inspect(value));
+
+    Ok(true)
+}
+
+
+// Breaks a value down into labeled decimal, hex and binary forms, for looking at bit patterns.
+// The same 32 bits are also shown as both signed and unsigned integers, since they are ambiguous.
+fn format_inspect(value: f64) -> String {
+    let hex = format_integer(value, 16, 32);
+    let binary = format_integer(value, 2, 32);
+
+    // Pad the leading group of binary digits out to a whole nibble.
+    let leading_digits = binary.find('_').unwrap_or(binary.len());
+    let binary = "0".repeat(3 - (leading_digits + 3) % 4) + &binary;
+
+    let sign = if value < 0.0 {
+        "negative"
+    } else if value > 0.0 {
+        "positive"
+    } else if value == 0.0 {
+        "zero"
+    } else {
+        "none"
+    };
+
+    format!("decimal    {}\nhex        0x{:0>8}\nbinary     {}\nsigned     {}\nunsigned   {}\nsign       {}\nmagnitude  {}\n",
+            value, hex, binary, ops::to_int(value), ops::to_uint(value), sign, value.abs())
+}
+
+
+// Values are always numbers, so char(code) is a display directive rather than a function.
+fn char_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    if tokenizer.peek().is_none() {
+        return Err(String::from("Usage: char(<code point>)"));
+    }
+
+    let expression = expr::parse(tokenizer, false, &context.settings)?;
+    let value = expr::evaluate(&expression, context)?;
+
+    println!("{}", format_char(value));
+
+    Ok(true)
+}
+
+
+// Shows the character for a Unicode code point, or the replacement character if it is not valid.
+fn format_char(value: f64) -> String {
+    let char = if value.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&value) {
+        std::char::from_u32(value as u32)
+    } else {
+        None
+    };
+
+    match char {
+        Some(char) => char.to_string(),
+        None => format!("{} ({} is not a valid code point)", std::char::REPLACEMENT_CHARACTER, value),
+    }
+}
+
+
+fn factor_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    if tokenizer.peek().is_none() {
+        return Err(String::from("Usage: factor <expression>"));
+    }
+
+    let expression = expr::parse(tokenizer, false, &context.settings)?;
+    let value = expr::evaluate(&expression, context)?;
+
+    println!("{}", format_factors(value)?);
+
+    Ok(true)
+}
+
+
+// Trial division up to the square root of this takes well under a second.
+const MAX_FACTOR: f64 = 1e12;
+
+
+// Formats the prime factorization of a value, such as 2^3 * 3^2 * 5 for 360.
+fn format_factors(value: f64) -> Result<String, String> {
+    if !(1.0..=MAX_FACTOR).contains(&value) || value.fract() != 0.0 {
+        return Err(format!("Can only factor whole numbers between 1 and {}.", MAX_FACTOR));
+    }
+
+    let factors = ops::prime_factors(value as u64);
+
+    if factors.is_empty() {
+        return Ok(String::from("1"));
+    }
+
+    Ok(factors.iter()
+              .map(|(prime, exponent)| if *exponent == 1 { prime.to_string() } else { format!("{}^{}", prime, exponent) })
+              .collect::<Vec<String>>()
+              .join(" * "))
+}
+
+
+fn gcd_command(tokenizer: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    let result = fold_integers("gcd", tokenizer, |x, y| Some(ops::gcd(x, y)))?;
+
+    println!("{}", result);
+
+    Ok(true)
+}
+
+
+fn lcm_command(tokenizer: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    let result = fold_integers("lcm", tokenizer, ops::lcm)?;
+
+    println!("{}", result);
+
+    Ok(true)
+}
+
+
+// Reduces a list of whole numbers with a binary operation that returns None on overflow.
+fn fold_integers(name: &str, tokenizer: &mut Peekable<Tokenizer>, operation: fn(u64, u64) -> Option<u64>) -> Result<u64, String> {
+    let usage = format!("Usage: {} <list of whole numbers>", name);
+
+    let mut values = vec![];
+
+    for token in tokenizer {
+        match token {
+            Ok(Token::Number(value)) if value.fract() == 0.0 && value <= ops::MAX_EXACT_INTEGER => values.push(value as u64),
+            _ => return Err(usage),
+        }
+    }
+
+    let (first, rest) = values.split_first().ok_or(usage)?;
+
+    rest.iter().try_fold(*first, |result, value| operation(result, *value))
+                .ok_or_else(|| format!("The {} is too large to represent.", name))
+}
+
+
+fn convert_command(tokenizer: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    let value = convert_units(tokenizer)?;
+
+    println!("{}", value);
+
+    Ok(true)
+}
+
+
+// Parses <number> <from unit> <to unit>, where the number may be negative.
+fn convert_units(tokenizer: &mut Peekable<Tokenizer>) -> Result<String, String> {
+    const USAGE: &str = "Usage: convert <number> <from unit> <to unit>";
+
+    let is_negative = matches!(tokenizer.peek(), Some(Ok(Token::Operator(op))) if op == "-");
+
+    if is_negative {
+        tokenizer.next();
+    }
+
+    let tokens: Vec<_> = tokenizer.collect();
+
+    match tokens.as_slice() {
+        [ Ok(Token::Number(value)), Ok(Token::Text(from)), Ok(Token::Text(to)) ] => {
+            let value = if is_negative { -value } else { *value };
+
+            units::convert(value, from, to).map(|result| format!("{} {}", format_shortest(result), to))
+        }
+
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+
+fn stat_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    let text = stat(tokenizer, context)?;
+
+    print!("{}", text);
+
+    Ok(true)
+}
+
+
+// Adds a data point, clears them all, or with no argument reports statistics of the points so far.
+fn stat(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<String, String> {
+    match tokenizer.peek() {
+        None => format_stats(&context.stat_values, &context.settings),
+
+        Some(Ok(Token::Text("clear"))) => {
+            context.stat_values.clear();
+            Ok(String::from("Cleared data points.\n"))
+        }
+
+        _ => {
+            let value = expr::parse(tokenizer, false, &context.settings).and_then(|expression| expr::evaluate(&expression, context))?;
+
+            context.stat_values.push(value);
+
+            Ok(format!("{} data point{}\n", context.stat_values.len(), if context.stat_values.len() == 1 { "" } else { "s" }))
+        }
+    }
+}
+
+
+// Reports the standard deviation of a sample, dividing by one less than the count.
+// A single data point has no spread, so that case reports zero rather than NaN.
+fn format_stats(values: &[f64], settings: &Settings) -> Result<String, String> {
+    if values.is_empty() {
+        return Err(String::from("No data points. Usage: stat <expression> | stat clear"));
+    }
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let variance = if values.len() > 1 {
+        values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (count - 1.0)
+    } else {
+        0.0
+    };
+
+    Ok([ ("count", count), ("mean", mean), ("min", min), ("max", max), ("stddev", variance.sqrt()) ]
+           .iter()
+           .map(|(name, value)| format!("{} = {}\n", name, format_number(*value, settings)))
+           .collect())
+}
+
+
+fn tokens_command(tokenizer: &mut Peekable<Tokenizer>, _: &mut Context) -> Result<bool, String> {
+    print!("{}", format_tokens(tokenizer));
+
+    Ok(true)
+}
+
+
+// Lists the token stream one per line, stopping at the first error.
+fn format_tokens(tokenizer: &mut Peekable<Tokenizer>) -> String {
+    let mut result = String::new();
+
+    for token in tokenizer {
+        match token {
+            Ok(token) => result += &format!("{}\n", token),
+            Err(message) => { result += &format!("{}\n", message); break; }
+        }
+    }
+
+    result
+}
+
+
+// Commands named after a setting change it when given a value, then describe its current state.
+fn setting_command(name: &str, tokenizer: &mut Peekable<Tokenizer>, context: &mut Context, describe: fn(&Settings) -> String) -> Result<bool, String> {
+    if tokenizer.peek().is_some() {
+        context.settings.set(name, tokenizer)?;
+    }
+
+    println!("{}", describe(&context.settings));
+
+    Ok(true)
+}
+
+
+fn base_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("base", tokenizer, context, |settings| format!("Using base {}", settings.get("base").unwrap()))
+}
+
+
+fn inbase_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    let text = evaluate_in_base(tokenizer, context)?;
+
+    println!("{}", text);
+
+    Ok(true)
+}
+
+
+// Evaluates an expression and formats the result in a one-off number base, leaving the base setting unchanged.
+fn evaluate_in_base(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<String, String> {
+    const USAGE: &str = "Usage: inbase <number base between 2 and 36> <expression>";
+
+    let base = match tokenizer.next() {
+        Some(Ok(Token::Number(base))) if (2.0..=36.0).contains(&base) && base.fract() == 0.0 => base as u32,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    if tokenizer.peek().is_none() {
+        return Err(String::from(USAGE));
+    }
+
+    let expression = expr::parse(tokenizer, false, &context.settings)?;
+    let value = expr::evaluate(&expression, context)?;
+
+    let mut settings = context.settings.clone();
+
+    settings.bases = vec![ base ];
+
+    Ok(format_number(value, &settings))
+}
+
+
+fn epsilon_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("epsilon", tokenizer, context, |settings| format!("Using epsilon {}", settings.get("epsilon").unwrap()))
+}
+
+
+fn basesep_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("basesep", tokenizer, context, |settings| format!("Using base separator {}", settings.get("basesep").unwrap()))
+}
+
+
+fn timeout_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("timeout", tokenizer, context, |settings| match settings.timeout {
+        Some(timeout) => format!("Evaluation times out after {} ms", timeout),
+        None => String::from("Evaluation never times out"),
+    })
+}
+
+
+fn outrange_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("outrange", tokenizer, context, |settings| match settings.output_range {
+        Some((min, max)) => format!("Clamping decimal output between {} and {}", min, max),
+        None => String::from("Decimal output is not clamped"),
+    })
+}
+
+
+fn echo_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("echo", tokenizer, context, |settings| if settings.echo {
+        String::from("Echoing each line before its result")
+    } else {
+        String::from("Results are shown without echoing the line")
+    })
+}
+
+
+fn intmode_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    setting_command("intmode", tokenizer, context, |settings| if settings.integer_mode {
+        String::from("Integer arithmetic is exact within 64 bit range")
+    } else {
+        String::from("All arithmetic uses 64 bit floating point")
+    })
+}
+
+
+fn set_command(tokenizer: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    match tokenizer.next() {
+        Some(Ok(Token::Text(name))) => {
+            context.settings.set(name, tokenizer)?;
+
+            println!("{} = {}", name, context.settings.get(name).unwrap());
+        }
+
+        _ => return Err(String::from("Usage: set <name> <value>")),
+    }
+
+    Ok(true)
+}
+
+
+fn settings_command(_: &mut Peekable<Tokenizer>, context: &mut Context) -> Result<bool, String> {
+    print!("{}", format_settings(&context.settings));
+
+    Ok(true)
+}
+
+
+fn format_settings(settings: &Settings) -> String {
+    settings.list()
+            .iter()
+            .map(|(name, value)| format!("{} = {}\n", name, value))
+            .collect()
+}
+
+
+// With echo turned on, results follow the line that produced them, so argument file output is easier to read.
+fn format_result(line: &str, value: expr::Value, settings: &Settings) -> String {
+    if settings.echo {
+        format!("{} = {}", line.trim(), format_value(value, settings))
+    } else {
+        format_value(value, settings)
+    }
+}
+
+
+fn format_number(value: f64, settings: &Settings) -> String {
+    format_value(expr::Value::Float(value), settings)
+}
+
+
+// Output range clamping only applies to decimal output, so other bases still show the raw value.
+// Integer mode results keep every digit in decimal output, while other bases still go through f64.
+fn format_value(exact: expr::Value, settings: &Settings) -> String {
+    let value = exact.to_f64();
+
+    let decimal = match settings.output_range {
+        Some((min, max)) => value.clamp(min, max),
+        None => value,
+    };
+
+    let shortest = match (exact, settings.output_range) {
+        (expr::Value::Integer(integer), None) => integer.to_string(),
+        _ => format_shortest(decimal),
+    };
+
+    settings.bases.iter()
+                  .map(|base| match (base, settings.precision) {
+                      (10, None)            => shortest.clone(),
+                      (10, Some(precision)) => format!("{:.*}", precision, decimal),
+                      _ if settings.raw     => format_integer(value, *base, settings.width_for(value)).replace('_', ""),
+                      (16, _)               => format!("0x{}", format_integer(value, *base, settings.width_for(value))),
+                      _                     => format_integer(value, *base, settings.width_for(value)),
+                  })
+                  .collect::<Vec<String>>()
+                  .join(&settings.base_separator)
+}
+
+
+// Rust formats floats as the shortest decimal string that parses back to exactly the same value,
+// so default output can always be copied back into an expression without losing precision.
+fn format_shortest(value: f64) -> String {
+    let text = format!("{}", value);
+
+    debug_assert!(roundtrips(&text, value), "{} does not round trip", text);
+
+    text
+}
+
+
+fn roundtrips(text: &str, value: f64) -> bool {
+    match text.parse::<f64>() {
+        Ok(parsed) => parsed.to_bits() == value.to_bits() || (parsed.is_nan() && value.is_nan()),
+        Err(_) => false,
+    }
+}
+
+
+// Formats the low bits of a value as an unsigned integer, so negative values appear as two's complement.
+fn format_integer(value: f64, base: u32, width: u32) -> String {
+    let mut value = value as i64 as u64 & (u64::MAX >> (64 - width));
+    let mut digits = vec![];
+
+    loop {
+        digits.push(std::char::from_digit((value % base as u64) as u32, base).unwrap());
+        value /= base as u64;
+
+        if value == 0 {
+            break;
+        }
+    }
+
+    let mut result = String::new();
+
+    for (i, digit) in digits.iter().enumerate().rev() {
+        result.push(*digit);
+
+        if base == 2 && i > 0 && i % 4 == 0 {
+            result.push('_');
+        }
+    }
+
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::IntegerWidth;
+
+
+    #[test]
+    fn load_file() {
+        fs::write("load_test.txt", "f(x) = x*2\nbogus(\ng = f(21)\n").unwrap();
+
+        let mut context = Context::new();
+
+        evaluate_file("load_test.txt", &mut context).unwrap();
+
+        assert_eq!(context.functions.len(), 2);
+        let g = context.functions["g"].clone();
+        assert_eq!(expr::evaluate(&g.expression, &mut context).unwrap(), 42.0);
+
+        fs::remove_file("load_test.txt").unwrap();
+
+        assert!(evaluate_file("load_test.txt", &mut context).is_err());
+    }
+
+
+    #[test]
+    fn history_file() {
+        let _ = fs::remove_file("history_test.txt");
+
+        let mut context = Context::new();
+
+        // A missing file loads as empty history.
+        context.load_history("history_test.txt").unwrap();
+        assert!(context.history().is_empty());
+
+        context.add_history("x = 5\n");
+        context.add_history("   ");
+        context.add_history("x * 2");
+        assert_eq!(context.history(), [ "x = 5", "x * 2" ]);
+
+        context.save_history("history_test.txt").unwrap();
+        assert_eq!(fs::read_to_string("history_test.txt").unwrap(), "x = 5\nx * 2\n");
+
+        // Saving again only appends lines added since the last save.
+        context.add_history("ls");
+        context.save_history("history_test.txt").unwrap();
+        context.save_history("history_test.txt").unwrap();
+        assert_eq!(fs::read_to_string("history_test.txt").unwrap(), "x = 5\nx * 2\nls\n");
+
+        // A new session loads the old lines ahead of its own, and doesn't save them twice.
+        let mut context = Context::new();
+
+        context.add_history("1 + 2");
+        context.load_history("history_test.txt").unwrap();
+        assert_eq!(context.history(), [ "x = 5", "x * 2", "ls", "1 + 2" ]);
+
+        context.save_history("history_test.txt").unwrap();
+        assert_eq!(fs::read_to_string("history_test.txt").unwrap(), "x = 5\nx * 2\nls\n1 + 2\n");
+
+        fs::remove_file("history_test.txt").unwrap();
+
+        // A directory can't be read or written as a history file.
+        assert!(context.load_history("src").unwrap_err().starts_with("Can't read src: "));
+        context.add_history("3");
+        assert!(context.save_history("src").unwrap_err().starts_with("Can't write src: "));
+    }
+
+
+    #[test]
+    fn multiline_history() {
+        let _ = fs::remove_file("multiline_history_test.txt");
+
+        let mut context = Context::new();
+
+        context.add_history("f(x) = (x +\n  1) *\r\n  2\n");
+        context.add_history("f(3)\n");
+        assert_eq!(context.history(), [ "f(x) = (x + 1) * 2", "f(3)" ]);
+
+        context.save_history("multiline_history_test.txt").unwrap();
+
+        let mut context = Context::new();
+
+        context.load_history("multiline_history_test.txt").unwrap();
+        assert_eq!(context.history(), [ "f(x) = (x + 1) * 2", "f(3)" ]);
+
+        fs::remove_file("multiline_history_test.txt").unwrap();
+    }
+
+
+    #[test]
+    fn semicolon_suppresses_output() {
+        fn printed(line: &str, context: &mut Context) -> Vec<f64> {
+            let mut values = vec![];
+            execute_line(line, context, &mut |value, _| values.push(value.to_f64())).unwrap();
+            values
+        }
+
+        let mut context = Context::new();
+
+        assert_eq!(printed("5", &mut context), vec![ 5.0 ]);
+        assert!(printed("5;", &mut context).is_empty());
+        assert!(printed("6 ;", &mut context).is_empty());
+
+        // Quiet results are still evaluated, so they update ans.
+        assert_eq!(printed("ans(1) * 2", &mut context), vec![ 12.0 ]);
+
+        assert!(printed("x = 2; y = 3;", &mut context).is_empty());
+        assert_eq!(printed("x + 1; x + y, x * y", &mut context), vec![ 5.0, 6.0 ]);
+        assert_eq!(printed("1; 2", &mut context), vec![ 2.0 ]);
+
+        assert_eq!(context.run("7;\n8"), vec![ Ok(None), Ok(Some(8.0)) ]);
+    }
+
+
+    #[test]
+    fn run_script() {
+        let mut context = Context::new();
+
+        assert_eq!(context.run("square(x) = x * x\nsquare(3) + 1\nsquare(y)"), vec![
+            Ok(None),
+            Ok(Some(10.0)),
+            Err(String::from("Unknown value y.")),
+        ]);
+
+        assert_eq!(context.run("1, 2, 3\n\ndivzero error\nq\n4"), vec![
+            Ok(Some(3.0)),
+            Ok(None),
+            Ok(None),
+            Ok(None),
+        ]);
+
+        assert_eq!(context.results.front(), Some(&3.0));
+        assert!(context.settings.divide_by_zero_is_error);
+
+        assert_eq!(context.run("memoize\n5"), vec![
+            Err(String::from("Usage: memoize <name>")),
+            Ok(Some(5.0)),
+        ]);
+
+        assert_eq!(context.last_error(), Some("Usage: memoize <name>"));
+    }
+
+
+    #[test]
+    fn last_error() {
+        let mut context = Context::new();
+
+        assert_eq!(context.last_error(), None);
+
+        assert!(evaluate_line("1/", &mut context).is_err());
+        assert_eq!(context.last_error(), Some("Invalid expression: unexpected end of input."));
+
+        evaluate_line("1 + 1", &mut context).unwrap();
+        assert_eq!(context.last_error(), Some("Invalid expression: unexpected end of input."));
+
+        context.run("2\nbogus");
+        assert_eq!(context.last_error(), Some("Unknown value bogus."));
+    }
+
+
+    #[test]
+    fn function_accessors() {
+        let mut context = Context::new();
+
+        evaluate_line("f(x, y) = x*y + 1", &mut context).unwrap();
+        evaluate_line("a = 2", &mut context).unwrap();
+
+        assert_eq!(context.function_names(), vec![ "a", "f" ]);
+
+        let function = context.get_function("f").unwrap();
+
+        assert_eq!(function.args, vec![ "x", "y" ]);
+        assert_eq!(function.body(), "+(*(x(),y()),1)");
+
+        assert_eq!(context.get_function("a").unwrap().body(), "2");
+        assert!(context.get_function("g").is_none());
+    }
+
+
+    #[test]
+    fn reserved_names() {
+        let mut context = Context::new();
+
+        assert_eq!(evaluate_line("ans = 5", &mut context).unwrap_err(), "Cannot define ans: that name is reserved.");
+        assert_eq!(evaluate_line("deriv(x) = x", &mut context).unwrap_err(), "Cannot define deriv: that name is reserved.");
+        assert_eq!(evaluate_line("fib(n) = n", &mut context).unwrap_err(), "Cannot define fib: that name is reserved.");
+        assert_eq!(evaluate_line("or(x) = x", &mut context).unwrap_err(), "Invalid expression: binary || operator is missing an operand.");
+        assert_eq!(evaluate_line("f(and) = 1", &mut context).unwrap_err(), "Invalid expression: unexpected end of input.");
+
+        assert!(context.functions.is_empty());
+
+        for (keyword, _) in expr::KEYWORDS.iter() {
+            assert!(expr::is_reserved_name(keyword));
+        }
+
+        assert!(!expr::is_reserved_name("answer"));
+    }
+
+
+    #[test]
+    fn bases_accessors() {
+        let mut context = Context::new();
+
+        assert_eq!(context.bases(), &[ 10 ]);
+
+        context.set_bases(&[ 2, 16 ]).unwrap();
+        assert_eq!(context.bases(), &[ 2, 16 ]);
+
+        assert_eq!(context.set_bases(&[ 1 ]).unwrap_err(), "Number bases must be between 2 and 36.");
+        assert_eq!(context.set_bases(&[ 37 ]).unwrap_err(), "Number bases must be between 2 and 36.");
+        assert_eq!(context.set_bases(&[]).unwrap_err(), "Number bases must be between 2 and 36.");
+        assert_eq!(context.set_bases(&[ 2, 3, 4, 5, 6, 7, 8, 9, 10 ]).unwrap_err(), "At most 8 different number bases can be displayed.");
+        assert_eq!(context.bases(), &[ 2, 16 ]);
+
+        context.set_bases(&[ 10, 16, 10, 2, 16 ]).unwrap();
+        assert_eq!(context.bases(), &[ 10, 16, 2 ]);
+    }
+
+
+    #[test]
+    fn builder() {
+        let context = Context::builder().base(16).precision(4).recursion_limit(1000).build().unwrap();
+
+        assert_eq!(context.bases(), &[ 16 ]);
+        assert_eq!(context.settings().precision, Some(4));
+        assert_eq!(context.settings().recursion_limit, 1000);
+        assert!(!context.settings().divide_by_zero_is_error);
+
+        let context = Context::builder().bases(&[ 10, 2 ]).build().unwrap();
+
+        assert_eq!(context.bases(), &[ 10, 2 ]);
+        assert_eq!(context.settings().precision, None);
+        assert_eq!(context.settings().recursion_limit, Settings::new().recursion_limit);
+
+        assert_eq!(Context::builder().base(37).build().err().unwrap(), "Number bases must be between 2 and 36.");
+    }
+
+
+    #[test]
+    fn number_formatting() {
+        let mut settings = Settings::new();
+
+        assert_eq!(format_number(1.0 / 3.0, &settings), "0.3333333333333333");
+        assert_eq!(format_number(255.0, &settings), "255");
+
+        settings.precision = Some(4);
+
+        assert_eq!(format_number(1.0 / 3.0, &settings), "0.3333");
+        assert_eq!(format_number(255.0, &settings), "255.0000");
+
+        settings.set_bases(&[ 2, 10, 16 ]).unwrap();
+
+        assert_eq!(format_number(255.0, &settings), "1111_1111  255.0000  0xff");
+    }
+
+
+    #[test]
+    fn shortest_output_roundtrips() {
+        let mut context = Context::new();
+
+        // Deterministic xorshift generator, so any failure is reproducible.
+        let mut state = 0x2545f4914f6cdd1du64;
+
+        let mut random_bits = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let special = [ 0.0, -0.0, 0.1, 1.0 / 3.0, f64::MIN_POSITIVE, 5e-324, f64::MAX, f64::EPSILON, 1e21, 123456789.0 ];
+
+        let random = (0..1000).map(|_| f64::from_bits(random_bits())).filter(|value| value.is_finite());
+
+        for value in special.iter().cloned().chain(random) {
+            let text = format_number(value, &context.settings);
+
+            assert!(roundtrips(&text, value), "{} does not round trip", text);
+
+            // Also check the text reads back in as the same value when typed as an expression.
+            let expression = expr::parse(&mut Tokenizer::new(&text).peekable(), false, &context.settings).unwrap();
+            let parsed = context.eval_with(&expression, &[]).unwrap();
+
+            assert_eq!(parsed.to_bits(), value.to_bits(), "{} does not round trip", text);
+        }
+
+        assert!(roundtrips("NaN", f64::NAN));
+        assert!(roundtrips("inf", f64::INFINITY));
+        assert!(!roundtrips("0.3333", 1.0 / 3.0));
+    }
+
+
+    #[test]
+    fn integer_width() {
+        let mut settings = Settings::new();
+
+        settings.set_bases(&[ 16 ]).unwrap();
+
+        assert_eq!(format_number(-1.0, &settings), "0xffffffff");
+        assert_eq!(format_number(0x12_3456_789a_u64 as f64, &settings), "0x3456789a");
+
+        settings.integer_width = IntegerWidth::Auto;
+
+        assert_eq!(format_number(255.0, &settings), "0xff");
+        assert_eq!(format_number(65535.0, &settings), "0xffff");
+        assert_eq!(format_number(65536.0, &settings), "0x10000");
+        assert_eq!(format_number(0x12_3456_789a_u64 as f64, &settings), "0x123456789a");
+
+        assert_eq!(format_number(-1.0, &settings), "0xff");
+        assert_eq!(format_number(-128.0, &settings), "0x80");
+        assert_eq!(format_number(-129.0, &settings), "0xff7f");
+        assert_eq!(format_number(-0x1_0000_0000_i64 as f64, &settings), "0xffffffff00000000");
+
+        settings.integer_width = IntegerWidth::Fixed(8);
+
+        assert_eq!(format_number(0x1ff as f64, &settings), "0xff");
+
+        settings.integer_width = IntegerWidth::Fixed(64);
+
+        assert_eq!(format_number(-2.0, &settings), "0xfffffffffffffffe");
+    }
+
+
+    #[test]
+    fn output_range() {
+        let mut context = Context::new();
+
+        evaluate_line("outrange -1 1", &mut context).unwrap();
+
+        assert_eq!(format_number(5.0, &context.settings), "1");
+        assert_eq!(format_number(-5.0, &context.settings), "-1");
+        assert_eq!(format_number(0.5, &context.settings), "0.5");
+        assert_eq!(format_number(f64::NAN, &context.settings), "NaN");
+
+        // Only the display is clamped, not the stored result.
+        evaluate_line("2 + 3", &mut context).unwrap();
+        assert_eq!(context.results.front(), Some(&5.0));
+
+        context.set_bases(&[ 10, 16 ]).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "1  0xff");
+
+        evaluate_line("outrange off", &mut context).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255  0xff");
+    }
+
+
+    #[test]
+    fn raw_output() {
+        let mut context = Context::new();
+
+        context.settings_mut().raw = true;
+
+        assert_eq!(format_number(1.0 + 2.0, &context.settings), "3");
+
+        context.set_bases(&[ 16 ]).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "ff");
+
+        context.set_bases(&[ 10, 16, 2 ]).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255  ff  11111111");
+
+        evaluate_line("set raw off", &mut context).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255  0xff  1111_1111");
+    }
+
+
+    #[test]
+    fn echo_output() {
+        let mut context = Context::new();
+
+        assert_eq!(format_result("1+2", expr::Value::Float(3.0), &context.settings), "3");
+
+        evaluate_line("echo on", &mut context).unwrap();
+        assert_eq!(format_result("1+2", expr::Value::Float(3.0), &context.settings), "1+2 = 3");
+        assert_eq!(format_result("  2 * 8 \n", expr::Value::Float(16.0), &context.settings), "2 * 8 = 16");
+
+        context.set_bases(&[ 10, 16 ]).unwrap();
+        assert_eq!(format_result("255", expr::Value::Float(255.0), &context.settings), "255 = 255  0xff");
+
+        evaluate_line("set echo off", &mut context).unwrap();
+        assert_eq!(format_result("255", expr::Value::Float(255.0), &context.settings), "255  0xff");
+    }
+
+
+    #[test]
+    fn integer_mode_output() {
+        let mut context = Context::new();
+
+        evaluate_line("intmode on", &mut context).unwrap();
+        assert!(context.settings.integer_mode);
+
+        assert_eq!(format_value(expr::Value::Integer(9007199254740995), &context.settings), "9007199254740995");
+        assert_eq!(format_value(expr::Value::Integer(-9007199254740995), &context.settings), "-9007199254740995");
+        assert_eq!(format_value(expr::Value::Float(2.5), &context.settings), "2.5");
+
+        context.set_bases(&[ 10, 16 ]).unwrap();
+        assert_eq!(format_value(expr::Value::Integer(255), &context.settings), "255  0xff");
+    }
+
+
+    #[test]
+    fn in_base() {
+        let mut context = Context::new();
+
+        context.set_bases(&[ 10, 2 ]).unwrap();
+
+        fn eval(line: &str, context: &mut Context) -> Result<String, String> {
+            evaluate_in_base(&mut Tokenizer::new(line).peekable(), context)
+        }
+
+        assert_eq!(eval("7 255", &mut context).unwrap(), "513");
+        assert_eq!(eval("36 35 * 36 + 35", &mut context).unwrap(), "zz");
+        assert_eq!(eval("16 255", &mut context).unwrap(), "0xff");
+        assert_eq!(eval("2 5", &mut context).unwrap(), "101");
+        assert_eq!(eval("10 1/4", &mut context).unwrap(), "0.25");
+
+        assert_eq!(eval("1 5", &mut context).unwrap_err(), "Usage: inbase <number base between 2 and 36> <expression>");
+        assert_eq!(eval("37 5", &mut context).unwrap_err(), "Usage: inbase <number base between 2 and 36> <expression>");
+        assert_eq!(eval("2.5 5", &mut context).unwrap_err(), "Usage: inbase <number base between 2 and 36> <expression>");
+        assert_eq!(eval("7", &mut context).unwrap_err(), "Usage: inbase <number base between 2 and 36> <expression>");
+        assert_eq!(eval("7 x", &mut context).unwrap_err(), "Unknown value x.");
+
+        assert_eq!(context.bases(), &[ 10, 2 ]);
+    }
+
+
+    #[test]
+    fn base_separator() {
+        let mut context = Context::new();
+
+        evaluate_line("base 10 16", &mut context).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255  0xff");
+
+        evaluate_line("basesep ' | '", &mut context).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255 | 0xff");
+
+        evaluate_line("basesep '\\t'", &mut context).unwrap();
+        assert_eq!(format_number(255.0, &context.settings), "255\t0xff");
+
+        assert!(evaluate_line("basesep bogus", &mut context).is_err());
+        assert_eq!(format_number(255.0, &context.settings), "255\t0xff");
+    }
+
+
+    #[test]
+    fn previous_results() {
+        let mut context = Context::new();
+
+        fn eval(expression: &str, context: &mut Context) -> Result<f64, String> {
+            let expression = expr::parse(&mut Tokenizer::new(expression).peekable(), false, &context.settings)?;
+            context.eval_with(&expression, &[])
+        }
+
+        assert_eq!(eval("ans", &mut context).unwrap_err(), "ans(1) is out of range: there are only 0 previous results.");
+
+        evaluate_line("10", &mut context).unwrap();
+        evaluate_line("20", &mut context).unwrap();
+        evaluate_line("f(x) = x + ans", &mut context).unwrap();
+        evaluate_line("30", &mut context).unwrap();
+
+        assert_eq!(eval("ans", &mut context).unwrap(), 30.0);
+        assert_eq!(eval("ans(1)", &mut context).unwrap(), 30.0);
+        assert_eq!(eval("ans(2)", &mut context).unwrap(), 20.0);
+        assert_eq!(eval("ans(1 + 2)", &mut context).unwrap(), 10.0);
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 31.0);
+
+        evaluate_line("ans(2) + ans(3)", &mut context).unwrap();
+
+        assert_eq!(eval("ans", &mut context).unwrap(), 30.0);
+        assert_eq!(eval("ans(2)", &mut context).unwrap(), 30.0);
+
+        assert_eq!(eval("ans(0)", &mut context).unwrap_err(), "ans() index must be a positive integer.");
+        assert_eq!(eval("ans(1.5)", &mut context).unwrap_err(), "ans() index must be a positive integer.");
+        assert_eq!(eval("ans(5)", &mut context).unwrap_err(), "ans(5) is out of range: there are only 4 previous results.");
+        assert_eq!(eval("ans(1, 2)", &mut context).unwrap_err(), "Wrong number of arguments for ans(): expected 0 or 1 but got 2.");
+
+        for i in 0..MAX_RESULTS * 2 {
+            evaluate_line(&i.to_string(), &mut context).unwrap();
+        }
+
+        assert_eq!(context.results.len(), MAX_RESULTS);
+        assert_eq!(eval("ans", &mut context).unwrap(), (MAX_RESULTS * 2 - 1) as f64);
+    }
+
+
+    #[test]
+    fn divzero() {
+        let mut context = Context::new();
+
+        assert!(!context.settings.divide_by_zero_is_error);
+
+        evaluate_line("divzero error", &mut context).unwrap();
+        assert!(context.settings.divide_by_zero_is_error);
+
+        assert!(evaluate_line("divzero bogus", &mut context).is_err());
+        assert!(context.settings.divide_by_zero_is_error);
+
+        evaluate_line("divzero inf", &mut context).unwrap();
+        assert!(!context.settings.divide_by_zero_is_error);
+
+        evaluate_line("divzero on", &mut context).unwrap();
+        assert!(context.settings.divide_by_zero_is_error);
+
+        assert_eq!(evaluate_line("divzero inf junk", &mut context).unwrap_err(), "Usage: divzero <error|inf|on|off>");
+        assert!(context.settings.divide_by_zero_is_error);
+
+        evaluate_line("divzero off", &mut context).unwrap();
+        assert!(!context.settings.divide_by_zero_is_error);
+    }
+
+
+    #[test]
+    fn export() {
+        let mut context = Context::new();
+
+        assert_eq!(format_export(&context), "[]\n");
+
+        evaluate_line("f(x, y) = x*y + 1", &mut context).unwrap();
+        evaluate_line("a = -2", &mut context).unwrap();
+        evaluate_line("g(n) | n<0 = 0 | 1 = f(n, a)", &mut context).unwrap();
+
+        assert_eq!(format_export(&context), concat!(
+            "[\n",
+            "  { \"name\": \"a\", \"args\": [], \"body\": \"-(2)\" },\n",
+            "  { \"name\": \"f\", \"args\": [\"x\", \"y\"], \"body\": \"((x * y) + 1)\" },\n",
+            "  { \"name\": \"g\", \"args\": [\"n\"], \"body\": \"((n < 0) ? 0 : (1 ? f(n, a) : (0/0)))\" }\n",
+            "]\n"));
+    }
+
+
+    #[test]
+    fn import() {
+        let mut context = Context::new();
+
+        evaluate_line("f(x, y) = x*y + 1", &mut context).unwrap();
+        evaluate_line("a = -2", &mut context).unwrap();
+        evaluate_line("g(n) | n<0 = 0 | n<10 = f(n, a) % 7", &mut context).unwrap();
+        evaluate_line("h(x) = -x% + 1/0", &mut context).unwrap();
+
+        let json = format_export(&context);
+
+        let mut imported = Context::new();
+
+        assert_eq!(import_functions(&json, &mut imported).unwrap(), Vec::<String>::new());
+        assert_eq!(imported.function_names(), context.function_names());
+
+        for name in [ "a", "f", "h" ] {
+            let original = context.get_function(name).unwrap();
+            let function = imported.get_function(name).unwrap();
+
+            assert_eq!(function.args, original.args);
+            assert_eq!(function.body(), original.body());
+        }
+
+        // The NaN fallthrough of a piecewise function is exported as 0/0, so compare results instead.
+        for n in [ -1.0, 3.0, 20.0 ] {
+            let call = expr::parse(&mut Tokenizer::new("g(n)").peekable(), false, &context.settings).unwrap();
+
+            let expected = context.eval_with(&call, &[ ("n", n) ]).unwrap();
+            let actual = imported.eval_with(&call, &[ ("n", n) ]).unwrap();
+
+            assert!(expected == actual || (expected.is_nan() && actual.is_nan()));
+        }
+
+        let errors = import_functions(concat!(
+            "[ { \"name\": \"b\", \"body\": \"3\" },",
+            "  { \"name\": \"c\", \"args\": [], \"body\": \"(\" },",
+            "  { \"name\": \"2\", \"args\": [], \"body\": \"3\" },",
+            "  { \"name\": \"d\", \"args\": [[\"x\"]], \"body\": \"3\" },",
+            "  { \"name\": \"e\", \"args\": \"x\", \"body\": \"3\" },",
+            "  { \"name\": \"f\" } ]"), &mut imported).unwrap();
+
+        assert_eq!(errors, vec![
+            "Entry 2: Invalid expression: unexpected end of input.",
+            "Entry 3: '2 = 3' is not a function definition.",
+            "Entry 4: Function arguments must be strings.",
+            "Entry 5: Expected an array \"args\" field.",
+            "Entry 6: Expected a string \"body\" field.",
+        ]);
+
+        assert_eq!(imported.get_function("b").unwrap().body(), "3");
+
+        assert_eq!(import_functions("{}", &mut imported).unwrap_err(), "Expected a JSON array of functions.");
+        assert_eq!(import_functions("[", &mut imported).unwrap_err(), "Unexpected end of JSON.");
+    }
+
+
+    #[test]
+    fn memoize() {
+        let mut context = Context::new();
+
+        fn eval(expression: &str, context: &mut Context) -> Result<f64, String> {
+            let expression = expr::parse(&mut Tokenizer::new(expression).peekable(), false, &context.settings)?;
+            context.eval_with(&expression, &[])
+        }
+
+        evaluate_line("fibo(n) = n < 2 ? n : fibo(n - 1) + fibo(n - 2)", &mut context).unwrap();
+
+        memoize_function("fibo", &mut context).unwrap();
+
+        // Each argument from 0 to 30 is only evaluated once, where plain recursion would make millions of calls.
+        assert_eq!(eval("fibo(30)", &mut context).unwrap(), 832040.0);
+        assert_eq!(context.function_calls, 31);
+        assert_eq!(context.memo_caches["fibo"].len(), 31);
+
+        assert_eq!(eval("fibo(30) + fibo(10)", &mut context).unwrap(), 832095.0);
+        assert_eq!(context.function_calls, 31);
+
+        // Redefining any function clears the caches, since results may depend on it.
+        evaluate_line("f(x) = x + a", &mut context).unwrap();
+        evaluate_line("a = 1", &mut context).unwrap();
+        memoize_function("f", &mut context).unwrap();
+
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 2.0);
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 2.0);
+
+        evaluate_line("a = 2", &mut context).unwrap();
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 3.0);
+
+        eval("(a = 3) * 0", &mut context).unwrap();
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 4.0);
+
+        evaluate_line("f(x) = x * 10", &mut context).unwrap();
+        assert_eq!(eval("f(1)", &mut context).unwrap(), 10.0);
+        assert!(context.memo_caches["fibo"].is_empty());
+
+        assert_eq!(memoize_function("g", &mut context).unwrap_err(), "Unknown function g.");
+    }
+
+
+    #[test]
+    fn answer_variable() {
+        let mut context = Context::new();
+
+        assert!(context.get_function("Ans").is_none());
+
+        evaluate_line("1 + 2", &mut context).unwrap();
+        assert_eq!(context.function_names(), vec![ "Ans" ]);
+        assert_eq!(context.get_function("Ans").unwrap().body(), "3");
+
+        evaluate_line("Ans * 10", &mut context).unwrap();
+        assert_eq!(context.get_function("Ans").unwrap().body(), "30");
+
+        // Definitions don't produce a result, so leave Ans alone.
+        evaluate_line("x = 5", &mut context).unwrap();
+        assert_eq!(context.function_names(), vec![ "Ans", "x" ]);
+        assert_eq!(context.get_function("Ans").unwrap().body(), "30");
+
+        // Ans can be overwritten like any other variable, until the next result replaces it.
+        evaluate_line("Ans = 7", &mut context).unwrap();
+        assert_eq!(context.run("Ans + 1, Ans + 1"), vec![ Ok(Some(9.0)) ]);
+
+        evaluate_line("intmode on", &mut context).unwrap();
+        evaluate_line("9007199254740993", &mut context).unwrap();
+        evaluate_line("Ans + 2", &mut context).unwrap();
+        assert_eq!(context.get_function("Ans").unwrap().body(), "9007199254740995");
+    }
+
+
+    #[test]
+    fn memoize_across_lines() {
+        let mut context = Context::new();
+
+        evaluate_line("fibo(n) = n < 2 ? n : fibo(n - 1) + fibo(n - 2)", &mut context).unwrap();
+        evaluate_line("memoize fibo", &mut context).unwrap();
+        evaluate_line("fibo(20)", &mut context).unwrap();
+        assert_eq!(context.memo_caches["fibo"].len(), 21);
+
+        // Results rebind Ans, which should not count as a definition that invalidates the cache.
+        evaluate_line("1 + 2", &mut context).unwrap();
+        assert_eq!(context.memo_caches["fibo"].len(), 21);
+
+        evaluate_line("x = 5", &mut context).unwrap();
+        assert!(context.memo_caches["fibo"].is_empty());
+    }
+
+
+    #[test]
+    fn freeze() {
+        let mut context = Context::new();
+
+        evaluate_line("r = 5", &mut context).unwrap();
+        evaluate_line("area = 3 * r^2", &mut context).unwrap();
+        evaluate_line("f(x) = x", &mut context).unwrap();
+        evaluate_line("bad = 1 / undefined", &mut context).unwrap();
+
+        assert_eq!(freeze_function("area", &mut context).unwrap(), 75.0);
+        assert_eq!(context.get_function("area").unwrap().body(), "75");
+
+        evaluate_line("r = 10", &mut context).unwrap();
+
+        let area = context.functions["area"].clone();
+        assert_eq!(expr::evaluate(&area.expression, &mut context).unwrap(), 75.0);
+
+        assert_eq!(freeze_function("f", &mut context).unwrap_err(), "Can't freeze f(): only functions without parameters can be frozen.");
+        assert_eq!(freeze_function("g", &mut context).unwrap_err(), "Unknown function g.");
+        assert_eq!(freeze_function("bad", &mut context).unwrap_err(), "Unknown value undefined.");
+        assert_eq!(context.get_function("bad").unwrap().body(), "/(1,undefined())");
+    }
+
+
+    #[test]
+    fn timeout() {
+        let mut context = Context::new();
+
+        // Stays within the recursion limit, but makes 2^200 calls, so it can never finish.
+        evaluate_line("slow(n) = n < 1 ? 0 : slow(n - 1) + slow(n - 1)", &mut context).unwrap();
+        evaluate_line("timeout 50", &mut context).unwrap();
+
+        assert_eq!(context.settings.timeout, Some(50));
+        assert_eq!(evaluate_line("slow(200)", &mut context).unwrap_err(), "Evaluation timed out.");
+
+        evaluate_line("timeout off", &mut context).unwrap();
+        assert_eq!(context.settings.timeout, None);
+
+        evaluate_line("slow(3)", &mut context).unwrap();
+        assert_eq!(context.results[0], 0.0);
+    }
+
+
+    #[test]
+    fn epsilon() {
+        let mut context = Context::new();
+
+        evaluate_line("epsilon 0.01", &mut context).unwrap();
+        assert_eq!(context.settings.epsilon, 0.01);
+
+        assert!(evaluate_line("epsilon bogus", &mut context).is_err());
+        assert_eq!(context.settings.epsilon, 0.01);
+
+        evaluate_line("epsilon", &mut context).unwrap();
+        assert_eq!(context.settings.epsilon, 0.01);
+    }
+
+
+    #[test]
+    fn set_and_list_settings() {
+        let mut context = Context::new();
+
+        assert_eq!(format_settings(&context.settings), "base = 10\ndivzero = off\ntoint = truncate\nimplicitmul = off\nprecision = auto\nrecursion = 256\nstrict = off\nepsilon = 0.000000001\nbasesep = \"  \"\nraw = off\ntimeout = off\nwidth = 32\noutrange = off\nangle = radians\necho = off\nintmode = off\n");
+
+        evaluate_line("set base 2 16", &mut context).unwrap();
+        evaluate_line("set divzero on", &mut context).unwrap();
+        evaluate_line("set toint round", &mut context).unwrap();
+
+        assert_eq!(format_settings(&context.settings), "base = 2 16\ndivzero = on\ntoint = round\nimplicitmul = off\nprecision = auto\nrecursion = 256\nstrict = off\nepsilon = 0.000000001\nbasesep = \"  \"\nraw = off\ntimeout = off\nwidth = 32\noutrange = off\nangle = radians\necho = off\nintmode = off\n");
+
+        assert!(evaluate_line("set divzero bogus", &mut context).is_err());
+        assert!(evaluate_line("set bogus on", &mut context).is_err());
+        evaluate_line("base 8", &mut context).unwrap();
+        evaluate_line("divzero inf", &mut context).unwrap();
+
+        assert_eq!(format_settings(&context.settings), "base = 8\ndivzero = off\ntoint = round\nimplicitmul = off\nprecision = auto\nrecursion = 256\nstrict = off\nepsilon = 0.000000001\nbasesep = \"  \"\nraw = off\ntimeout = off\nwidth = 32\noutrange = off\nangle = radians\necho = off\nintmode = off\n");
+    }
+
+
+    #[test]
+    fn help_hides_internal_operators() {
+        let help = format_help();
+
+        for op in [ &ops::NEGATE, &ops::PERCENT, &ops::TERNARY, &ops::TERMINATOR ] {
+            assert!(ops::is_internal(op));
+        }
+
+        assert!(ops::OPERATORS.iter().all(|op| !ops::is_internal(op)));
+
+        assert!(!help.contains(ops::TERNARY.name));
+        assert!(!help.contains(ops::TERMINATOR.name));
+
+        assert!(help.contains("\nOperators:\n"));
+        assert!(help.contains("\nCommands:\n"));
+        assert!(help.contains(" ^^ "));
+        assert!(help.contains(" and "));
+        assert!(help.contains(" help "));
+    }
+
+
+    #[test]
+    fn help_categories() {
+        let categories = function_categories();
+
+        let mut names: Vec<&str> = categories.iter().flat_map(|category| category.1.clone()).collect();
+        let mut expected: Vec<&str> = ops::FUNCTIONS.iter().map(|function| function.name).collect();
+
+        names.sort_unstable();
+        expected.sort_unstable();
+
+        assert_eq!(names, expected);
+
+        assert!(categories.iter().all(|category| !category.0.is_empty() && !category.1.is_empty()));
+
+        assert_eq!(categories.iter().find(|category| category.0 == "Trig").unwrap().1.len(), 13);
+        assert_eq!(categories.iter().find(|category| category.0 == "Constants").unwrap().1, vec![ "e", "pi" ]);
+    }
+
+
+    #[test]
+    fn precof() {
+        assert_eq!(describe_precedence(ops::find_operator("^").unwrap()), "^ has Power precedence (level 19), right associative");
+        assert_eq!(describe_precedence(ops::find_operator("+").unwrap()), "+ has Addition precedence (level 16), left associative");
+        assert_eq!(describe_precedence(ops::find_operator("!").unwrap()), "! has Unary precedence (level 18), unary");
+        assert_eq!(describe_precedence(ops::find_operator("?").unwrap()), "? has Ternary precedence (level 4), right associative");
+        assert_eq!(describe_precedence(ops::find_operator("->").unwrap()), "-> has Implies precedence (level 5), right associative");
+        assert_eq!(describe_precedence(ops::find_operator("<?").unwrap()), "<? has MinMax precedence (level 14), left associative");
+    }
+
+
+    #[test]
+    fn inspect() {
+        assert_eq!(format_inspect(255.0), "decimal    255\nhex        0x000000ff\nbinary     1111_1111\nsigned     255\nunsigned   255\nsign       positive\nmagnitude  255\n");
+        assert_eq!(format_inspect(0.0), "decimal    0\nhex        0x00000000\nbinary     0000\nsigned     0\nunsigned   0\nsign       zero\nmagnitude  0\n");
+        assert_eq!(format_inspect(-2.5), "decimal    -2.5\nhex        0xfffffffe\nbinary     1111_1111_1111_1111_1111_1111_1111_1110\nsigned     -2\nunsigned   4294967294\nsign       negative\nmagnitude  2.5\n");
+        assert_eq!(format_inspect(-1.0), "decimal    -1\nhex        0xffffffff\nbinary     1111_1111_1111_1111_1111_1111_1111_1111\nsigned     -1\nunsigned   4294967295\nsign       negative\nmagnitude  1\n");
+        assert_eq!(format_inspect(37.0), "decimal    37\nhex        0x00000025\nbinary     0010_0101\nsigned     37\nunsigned   37\nsign       positive\nmagnitude  37\n");
+    }
+
+
+    #[test]
+    fn char() {
+        assert_eq!(format_char(65.0), "A");
+        assert_eq!(format_char(0x3c0 as f64), "\u{3c0}");
+        assert_eq!(format_char(0x10ffff as f64), "\u{10ffff}");
+        assert_eq!(format_char(0x110000 as f64), "\u{fffd} (1114112 is not a valid code point)");
+        assert_eq!(format_char(0xd800 as f64), "\u{fffd} (55296 is not a valid code point)");
+        assert_eq!(format_char(-1.0), "\u{fffd} (-1 is not a valid code point)");
+        assert_eq!(format_char(65.5), "\u{fffd} (65.5 is not a valid code point)");
+        assert_eq!(format_char(f64::NAN), "\u{fffd} (NaN is not a valid code point)");
+    }
+
+
+    #[test]
+    fn factor() {
+        assert_eq!(format_factors(360.0).unwrap(), "2^3 * 3^2 * 5");
+        assert_eq!(format_factors(97.0).unwrap(), "97");
+        assert_eq!(format_factors(1.0).unwrap(), "1");
+        assert_eq!(format_factors(2.0).unwrap(), "2");
+        assert_eq!(format_factors(1024.0).unwrap(), "2^10");
+        assert_eq!(format_factors(999999000001.0).unwrap(), "999999000001");
+        assert_eq!(format_factors(1e12).unwrap(), "2^12 * 5^12");
+
+        assert_eq!(format_factors(0.0).unwrap_err(), "Can only factor whole numbers between 1 and 1000000000000.");
+        assert_eq!(format_factors(-6.0).unwrap_err(), "Can only factor whole numbers between 1 and 1000000000000.");
+        assert_eq!(format_factors(2.5).unwrap_err(), "Can only factor whole numbers between 1 and 1000000000000.");
+        assert_eq!(format_factors(1e13).unwrap_err(), "Can only factor whole numbers between 1 and 1000000000000.");
+        assert_eq!(format_factors(f64::NAN).unwrap_err(), "Can only factor whole numbers between 1 and 1000000000000.");
+    }
+
+
+    #[test]
+    fn gcd_and_lcm() {
+        fn gcd(line: &str) -> Result<u64, String> {
+            fold_integers("gcd", &mut Tokenizer::new(line).peekable(), |x, y| Some(ops::gcd(x, y)))
+        }
+
+        fn lcm(line: &str) -> Result<u64, String> {
+            fold_integers("lcm", &mut Tokenizer::new(line).peekable(), ops::lcm)
+        }
+
+        assert_eq!(gcd("12 18 24"), Ok(6));
+        assert_eq!(gcd("7 13 99"), Ok(1));
+        assert_eq!(gcd("0 15"), Ok(15));
+        assert_eq!(gcd("42"), Ok(42));
+
+        assert_eq!(lcm("4 6 10"), Ok(60));
+        assert_eq!(lcm("3 5 7"), Ok(105));
+        assert_eq!(lcm("0 5"), Ok(0));
+
+        assert_eq!(lcm("9007199254740992 9007199254740991"), Err(String::from("The lcm is too large to represent.")));
+
+        assert_eq!(gcd(""), Err(String::from("Usage: gcd <list of whole numbers>")));
+        assert_eq!(gcd("12 1.5"), Err(String::from("Usage: gcd <list of whole numbers>")));
+        assert_eq!(lcm("4 -6"), Err(String::from("Usage: lcm <list of whole numbers>")));
+        assert_eq!(lcm("4 x"), Err(String::from("Usage: lcm <list of whole numbers>")));
+    }
+
+
+    #[test]
+    fn convert() {
+        fn convert(line: &str) -> Result<String, String> {
+            convert_units(&mut Tokenizer::new(line).peekable())
+        }
+
+        assert_eq!(convert("5 km m").unwrap(), "5000 m");
+        assert_eq!(convert("100 C F").unwrap(), "212 F");
+        assert_eq!(convert("-40 F C").unwrap(), "-40 C");
+
+        assert_eq!(convert("5 km lb").unwrap_err(), "Can't convert km (length) to lb (mass).");
+        assert_eq!(convert("5 km").unwrap_err(), "Usage: convert <number> <from unit> <to unit>");
+        assert_eq!(convert("km m").unwrap_err(), "Usage: convert <number> <from unit> <to unit>");
+        assert_eq!(convert("5 km m x").unwrap_err(), "Usage: convert <number> <from unit> <to unit>");
+    }
+
+
+    #[test]
+    fn stat() {
+        let mut context = Context::new();
+
+        fn run(line: &str, context: &mut Context) -> Result<String, String> {
+            super::stat(&mut Tokenizer::new(line).peekable(), context)
+        }
+
+        assert_eq!(run("", &mut context).unwrap_err(), "No data points. Usage: stat <expression> | stat clear");
+
+        assert_eq!(run("2", &mut context).unwrap(), "1 data point\n");
+        assert_eq!(run("4", &mut context).unwrap(), "2 data points\n");
+        assert_eq!(run("2 + 2", &mut context).unwrap(), "3 data points\n");
+        assert_eq!(run("6", &mut context).unwrap(), "4 data points\n");
+
+        assert_eq!(run("", &mut context).unwrap(), "count = 4\nmean = 4\nmin = 2\nmax = 6\nstddev = 1.632993161855452\n");
+
+        assert_eq!(run("x", &mut context).unwrap_err(), "Unknown value x.");
+        assert_eq!(context.stat_values.len(), 4);
+
+        assert_eq!(run("clear", &mut context).unwrap(), "Cleared data points.\n");
+        assert_eq!(run("", &mut context).unwrap_err(), "No data points. Usage: stat <expression> | stat clear");
+
+        run("-3", &mut context).unwrap();
+        assert_eq!(run("", &mut context).unwrap(), "count = 1\nmean = -3\nmin = -3\nmax = -3\nstddev = 0\n");
+    }
+
+
+    #[test]
+    fn tokens() {
+        let mut tokenizer = Tokenizer::new("0x1f + 2").peekable();
+        assert_eq!(format_tokens(&mut tokenizer), "Number 31\nOperator +\nNumber 2\n");
+
+        let mut tokenizer = Tokenizer::new("foo 1ee2 3").peekable();
+        assert_eq!(format_tokens(&mut tokenizer), "Text 'foo'\nInvalid numeric constant '1ee2'.\n");
+    }
+}
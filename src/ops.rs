@@ -1,5 +1,7 @@
 use std::f64;
 
+use crate::settings::{IntegerWidth, Settings};
+
 
 // Ordering of these enum values determines parser behavior.
 #[derive(Clone, Copy, Debug)]
@@ -9,13 +11,16 @@ pub enum Precedence {
     Terminator,
     Assign,
     Ternary,
+    Implies,
     LogicalOr,
+    LogicalXor,
     LogicalAnd,
     BinaryOr,
     BinaryXor,
     BinaryAnd,
     CompareEq,
     CompareDiff,
+    MinMax,
     Shift,
     Addition,
     Multiply,
@@ -27,32 +32,93 @@ pub enum Precedence {
 // Operators can be implemented using functions that take various numbers of parameters.
 #[derive(Debug)]
 pub enum OpFunction {
-    Nullary(fn()         -> f64),
-    Unary  (fn(f64)      -> f64),
-    Binary (fn(f64, f64) -> f64),
-    Lazy   (fn(f64)      -> usize),
+    Nullary(fn()              -> f64),
+    Unary  (fn(f64)           -> f64),
+    Binary (fn(f64, f64)      -> f64),
+    Ternary(fn(f64, f64, f64) -> f64),
+    Lazy   (fn(f64)           -> usize),
     Invalid,
 
+    // Variadic functions receive all their arguments as a slice, for operators with a range of arities.
+    Variadic(fn(&[f64]) -> f64),
+
+    // Configurable binary operators also receive the user settings, for instance to read the ~= tolerance.
+    Configurable(fn(&Settings, f64, f64) -> f64),
+    ConfigurableUnary(fn(&Settings, f64) -> f64),
+
     // Lazy operators take the value of their first parameter, and return the index of which
     // other parameter should be evaluated and used as the result of the expression. This
     // provides short circuit evaluation for logical || and && plus ternary ?: operators.
 }
 
 
-// Implementation of an operator or builtin function.
+// Implementation of an operator or builtin function. Most have a fixed arity, but
+// variadic functions accept any number of arguments from min_arity to max_arity.
 #[derive(Debug)]
 pub struct Operator {
     pub name:                 &'static str,
     pub precedence:           Precedence,
     pub arity:                u32,
+    pub min_arity:            u32,
+    pub max_arity:            u32,
     pub is_right_associative: bool,
     pub function:             OpFunction,
+    pub flags:                u32,
+
+    // Heading the operator is listed under by the help command. This is only for display.
+    pub category:             &'static str,
 }
 
 
+// Flags that change how an operator's arguments and results are handled during evaluation.
+pub const INTEGER_ARGUMENTS:   u32 = 1 << 0;    // Rounded rather than truncated if toint is round.
+pub const ANGLE_ARGUMENTS:     u32 = 1 << 1;    // Converted from degrees if that is the angle unit.
+pub const ANGLE_RESULT:        u32 = 1 << 2;    // Converted to degrees if that is the angle unit.
+pub const CONDITION_ARGUMENTS: u32 = 1 << 3;    // Treated as booleans, so NaN is an error in strict mode.
+
+
 pub type OperatorRef = &'static Operator;
 
 
+impl Operator {
+    // Bitwise operators and casts convert their arguments to integers.
+    pub fn has_integer_arguments(&self) -> bool {
+        self.flags & INTEGER_ARGUMENTS != 0
+    }
+
+
+    // Trig functions take and inverse trig functions return angles, which can be measured in degrees.
+    pub fn takes_angle(&self) -> bool {
+        self.flags & ANGLE_ARGUMENTS != 0
+    }
+
+
+    pub fn returns_angle(&self) -> bool {
+        self.flags & ANGLE_RESULT != 0
+    }
+
+
+    pub fn takes_conditions(&self) -> bool {
+        self.flags & CONDITION_ARGUMENTS != 0
+    }
+
+
+    pub fn accepts_arity(&self, count: usize) -> bool {
+        (self.min_arity as usize..=self.max_arity as usize).contains(&count)
+    }
+
+
+    // Describes how many arguments are expected, for error messages.
+    pub fn describe_arity(&self) -> String {
+        if self.min_arity == self.max_arity {
+            self.min_arity.to_string()
+        } else {
+            format!("{} to {}", self.min_arity, self.max_arity)
+        }
+    }
+}
+
+
 // Allow comparing operators directly against their string names.
 impl PartialEq<str> for Operator {
     fn eq(&self, other: &str) -> bool {
@@ -72,156 +138,814 @@ macro_rules! operators {
     ($($element:tt),*) => {
         [ $(operator! $element),* ]
     };
+
+    // Matches a table that is grouped into named categories.
+    // Entries can be followed by "with" and a list of evaluation flags.
+    ($($category:literal: [ $($element:tt $(with $($flag:ident)|+)?),* ]),*) => {
+        [ $($(Operator { category: $category, flags: 0 $($(| $flag)+)?, ..operator! $element }),*),* ]
+    };
 }
 
 
 macro_rules! operator {
     // Matches a nullary function.
     ($name:literal, || $expression:expr) => {
-        Operator { name: $name, precedence: Precedence::None, arity: 0, is_right_associative: false, function: OpFunction::Nullary(|| -> f64 { $expression }) }
+        Operator { name: $name, precedence: Precedence::None, arity: 0, min_arity: 0, max_arity: 0, is_right_associative: false, function: OpFunction::Nullary(|| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a unary function that reads user settings, identified by "configurable" marker keyword.
+    ($name:literal, configurable |$settings:ident, $x:ident| $expression:expr) => {
+        Operator { name: $name, precedence: Precedence::None, arity: 1, min_arity: 1, max_arity: 1, is_right_associative: false, function: OpFunction::ConfigurableUnary(|$settings: &Settings, $x: f64| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a binary function that reads user settings.
+    ($name:literal, configurable |$settings:ident, $x:ident, $y:ident| $expression:expr) => {
+        Operator { name: $name, precedence: Precedence::None, arity: 2, min_arity: 2, max_arity: 2, is_right_associative: false, function: OpFunction::Configurable(|$settings: &Settings, $x: f64, $y: f64| -> f64 { $expression }), flags: 0, category: "" }
     };
 
     // Matches a unary function.
     ($name:literal, |$x:ident| $expression:expr) => {
-        Operator { name: $name, precedence: Precedence::None, arity: 1, is_right_associative: false, function: OpFunction::Unary(|$x: f64| -> f64 { $expression }) }
+        Operator { name: $name, precedence: Precedence::None, arity: 1, min_arity: 1, max_arity: 1, is_right_associative: false, function: OpFunction::Unary(|$x: f64| -> f64 { $expression }), flags: 0, category: "" }
     };
 
     // Matches a unary operator.
     ($name:literal, $precedence:expr, |$x:ident| $expression:expr) => {
-        Operator { name: $name, precedence: $precedence, arity: 1, is_right_associative: false, function: OpFunction::Unary(|$x: f64| -> f64 { $expression }) }
+        Operator { name: $name, precedence: $precedence, arity: 1, min_arity: 1, max_arity: 1, is_right_associative: false, function: OpFunction::Unary(|$x: f64| -> f64 { $expression }), flags: 0, category: "" }
     };
 
     // Matches a binary function.
     ($name:literal, |$x:ident, $y:ident| $expression:expr) => {
-        Operator { name: $name, precedence: Precedence::None, arity: 2, is_right_associative: false, function: OpFunction::Binary(|$x: f64, $y: f64| -> f64 { $expression }) }
+        Operator { name: $name, precedence: Precedence::None, arity: 2, min_arity: 2, max_arity: 2, is_right_associative: false, function: OpFunction::Binary(|$x: f64, $y: f64| -> f64 { $expression }), flags: 0, category: "" }
     };
 
     // Matches a binary operator.
     ($name:literal, $precedence:expr, |$x:ident, $y:ident| $expression:expr) => {
-        Operator { name: $name, precedence: $precedence, arity: 2, is_right_associative: false, function: OpFunction::Binary(|$x: f64, $y: f64| -> f64 { $expression }) }
+        Operator { name: $name, precedence: $precedence, arity: 2, min_arity: 2, max_arity: 2, is_right_associative: false, function: OpFunction::Binary(|$x: f64, $y: f64| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a ternary function.
+    ($name:literal, |$x:ident, $y:ident, $z:ident| $expression:expr) => {
+        Operator { name: $name, precedence: Precedence::None, arity: 3, min_arity: 3, max_arity: 3, is_right_associative: false, function: OpFunction::Ternary(|$x: f64, $y: f64, $z: f64| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a right associative binary operator, identified by "right" marker keyword.
+    ($name:literal, $precedence:expr, right |$x:ident, $y:ident| $expression:expr) => {
+        Operator { name: $name, precedence: $precedence, arity: 2, min_arity: 2, max_arity: 2, is_right_associative: true, function: OpFunction::Binary(|$x: f64, $y: f64| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a binary operator that reads user settings, identified by "configurable" marker keyword.
+    ($name:literal, $precedence:expr, configurable |$settings:ident, $x:ident, $y:ident| $expression:expr) => {
+        Operator { name: $name, precedence: $precedence, arity: 2, min_arity: 2, max_arity: 2, is_right_associative: false, function: OpFunction::Configurable(|$settings: &Settings, $x: f64, $y: f64| -> f64 { $expression }), flags: 0, category: "" }
+    };
+
+    // Matches a function taking a range of argument counts, identified by "variadic" marker keyword.
+    ($name:literal, variadic $min_arity:literal ..= $max_arity:literal, |$args:ident| $expression:expr) => {
+        Operator { name: $name, precedence: Precedence::None, arity: $min_arity, min_arity: $min_arity, max_arity: $max_arity, is_right_associative: false, function: OpFunction::Variadic(|$args: &[f64]| -> f64 { $expression }), flags: 0, category: "" }
     };
 
     // Matches a lazily evaluated operator, identified by "lazy" marker keyword.
     ($name:literal, $precedence:expr, $arity:literal, lazy |$x:ident| $expression:expr) => {
-        Operator { name: $name, precedence: $precedence, arity: $arity, is_right_associative: false, function: OpFunction::Lazy(|$x: f64| -> usize { $expression }) }
+        Operator { name: $name, precedence: $precedence, arity: $arity, min_arity: $arity, max_arity: $arity, is_right_associative: false, function: OpFunction::Lazy(|$x: f64| -> usize { $expression }), flags: 0, category: "" }
     };
 
     // Matches a special operator that does not have any evaluation function.
     ($name:literal, $precedence:expr, $arity:literal, $is_right_associative:literal) => {
-        Operator { name: $name, precedence: $precedence, arity: $arity, is_right_associative: $is_right_associative, function: OpFunction::Invalid }
+        Operator { name: $name, precedence: $precedence, arity: $arity, min_arity: $arity, max_arity: $arity, is_right_associative: $is_right_associative, function: OpFunction::Invalid, flags: 0, category: "" }
     };
 }
 
 
 // Helpers for converting values between different types.
+// NaN is neither true nor false, but is treated as false so (0/0) ? 1 : 2 takes the else branch.
+// In strict mode the evaluator reports an error before NaN ever reaches this.
 fn to_bool(x: f64) -> bool {
-    x != 0.0
+    x != 0.0 && !x.is_nan()
 }
 
 fn to_float(x: bool) -> f64 {
     if x { 1.0 } else { 0.0 }
 }
 
-fn to_int(x: f64) -> i32 {
+pub fn to_int(x: f64) -> i32 {
     x as i64 as i32
 }
 
-fn to_uint(x: f64) -> u32 {
+pub fn to_uint(x: f64) -> u32 {
     x as i64 as u32
 }
 
 
-pub static OPERATORS: [Operator; 27] = operators![
-    // Special markers that should never actually be evaluated.
-    { "(",   Precedence::Brace,      0, false },
-    { ")",   Precedence::Brace,      0, false },
-    { "=",   Precedence::Assign,     2, false },
-
-    // Component parts of the ternary ?: operator.
-    { "?",   Precedence::Ternary,    2, true },
-    { ":",   Precedence::Ternary,    2, true },
-
-    // Boolean operators.
-    { "||",  Precedence::LogicalOr,  2, lazy |x| if to_bool(x) {0} else {1} },
-    { "&&",  Precedence::LogicalAnd, 2, lazy |x| if to_bool(x) {1} else {0} },
-    { "!",   Precedence::Unary,         |x| to_float(!to_bool(x)) },
-
-    // Bitwise operators.
-    { "|",   Precedence::BinaryOr,      |x, y| (to_int(x)  |  to_int(y))        as f64 },
-    { "^^",  Precedence::BinaryXor,     |x, y| (to_int(x)  ^  to_int(y))        as f64 },
-    { "&",   Precedence::BinaryAnd,     |x, y| (to_int(x)  &  to_int(y))        as f64 },
-    { "<<",  Precedence::Shift,         |x, y| (to_int(x)  << (to_int(y) & 31)) as f64 },
-    { ">>",  Precedence::Shift,         |x, y| (to_uint(x) >> (to_int(y) & 31)) as f64 },
-    { ">>>", Precedence::Shift,         |x, y| (to_int(x)  >> (to_int(y) & 31)) as f64 },
-    { "~",   Precedence::Unary,         |x|    !to_int(x)                       as f64 },
-
-    // Comparisons
-    { "==",  Precedence::CompareEq,     |x, y| to_float(x == y) },
-    { "!=",  Precedence::CompareEq,     |x, y| to_float(x != y) },
-    { "<",   Precedence::CompareDiff,   |x, y| to_float(x < y)  },
-    { ">",   Precedence::CompareDiff,   |x, y| to_float(x > y)  },
-    { "<=",  Precedence::CompareDiff,   |x, y| to_float(x <= y) },
-    { ">=",  Precedence::CompareDiff,   |x, y| to_float(x >= y) },
-
-    // Arithmetic.
-    { "+",   Precedence::Addition,      |x, y| x + y },
-    { "-",   Precedence::Addition,      |x, y| x - y },
-    { "*",   Precedence::Multiply,      |x, y| x * y },
-    { "/",   Precedence::Multiply,      |x, y| x / y },
-    { "%",   Precedence::Multiply,      |x, y| x.rem_euclid(y) },
-    { "^",   Precedence::Power,         |x, y| x.powf(y) }
+// Integer powers of integers are computed by repeated squaring, which is exact as long as the
+// result fits in the 53 bit mantissa, since every intermediate product is then smaller still.
+// Anything else, or results too large to be exact, falls back to powf.
+fn power(x: f64, y: f64) -> f64 {
+    if x.fract() == 0.0 && y.fract() == 0.0 && (0.0..=64.0).contains(&y) && x.abs() <= MAX_EXACT_INTEGER {
+        let mut result = 1.0;
+        let mut base = x;
+        let mut exponent = y as u32;
+
+        while exponent > 0 {
+            if exponent & 1 != 0 {
+                result *= base;
+            }
+
+            exponent >>= 1;
+
+            if exponent > 0 {
+                base *= base;
+            }
+        }
+
+        if result.abs() <= MAX_EXACT_INTEGER {
+            return result;
+        }
+    }
+
+    x.powf(y)
+}
+
+
+// Hermite interpolation from 0 to 1 as x moves from edge0 to edge1. If the edges are
+// equal this becomes a step function, returning 0 below the edge and 1 at or above it.
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    if edge0 == edge1 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+
+    t * t * (3.0 - 2.0 * t)
+}
+
+
+// Quotient matching the x % y remainder, so idiv(x, y) * y + imod(x, y) == x. Truncating x / y
+// would round independently of the remainder, giving idiv(1, 0.1) = 10 alongside imod(1, 0.1) = 0.09999999999999995.
+// Dividing by zero has no remainder to work from, so returns infinity or NaN like the / operator.
+fn truncated_divide(x: f64, y: f64) -> f64 {
+    if y == 0.0 {
+        return x / y;
+    }
+
+    ((x - x % y) / y).round()
+}
+
+
+// Wraps x into the half-open range [lo, hi), for example to normalize angles. An empty range returns NaN.
+// Rounding can land a value just below lo exactly on hi, which wraps around to lo.
+fn wrap(x: f64, lo: f64, hi: f64) -> f64 {
+    if lo >= hi {
+        return f64::NAN;
+    }
+
+    let result = lo + (x - lo).rem_euclid(hi - lo);
+
+    if result >= hi { lo } else { result }
+}
+
+
+// Used by the ~= operator, and public so tests and embedders can compare results the same way.
+// The tolerance is relative, but values close to zero are instead compared against it as an
+// absolute tolerance, so tiny rounding errors around zero still match.
+// Infinities only match themselves, and NaN is not approximately equal to anything.
+pub fn approx_eq(x: f64, y: f64, tolerance: f64) -> bool {
+    x == y || (x.is_finite() && y.is_finite() && (x - y).abs() <= tolerance * x.abs().max(y.abs()).max(1.0))
+}
+
+
+// Shared by the min and max functions plus the <? and >? operators.
+fn minimum(x: f64, y: f64) -> f64 {
+    if x < y {x} else {y}
+}
+
+fn maximum(x: f64, y: f64) -> f64 {
+    if x > y {x} else {y}
+}
+
+
+// Sums the base 10 digits of the integer part of a value, ignoring its sign.
+fn digit_sum(x: f64) -> f64 {
+    let mut value = x.trunc().abs() as u64;
+    let mut sum = 0;
+
+    while value > 0 {
+        sum += value % 10;
+        value /= 10;
+    }
+
+    sum as f64
+}
+
+
+// Wraps a value to an unsigned integer of the specified number of bits.
+fn to_bits(x: f64, width: u32) -> u64 {
+    x as i64 as u64 & (u64::MAX >> (64 - width))
+}
+
+
+// Reverses the order of bits within the integer width, so the result depends on the width setting.
+fn bit_reverse(x: f64, width: u32) -> f64 {
+    (to_bits(x, width).reverse_bits() >> (64 - width)) as f64
+}
+
+
+// Reverses the order of bytes within the integer width, so the result depends on the width setting.
+fn swap_bytes(x: f64, width: u32) -> f64 {
+    (to_bits(x, width).swap_bytes() >> (64 - width)) as f64
+}
+
+
+// Bit manipulation functions work on integers of the width setting, wrapping bit indexes around
+// within it like shift counts do for the 32 bit operators. Auto width allows any of the 64 bits,
+// so setting a high bit widens the value as needed.
+fn bit_width(settings: &Settings) -> u32 {
+    match settings.integer_width {
+        IntegerWidth::Fixed(width) => width,
+        IntegerWidth::Auto => 64,
+    }
+}
+
+
+fn bit_mask(n: f64, width: u32) -> u64 {
+    1 << (to_uint(n) & (width - 1))
+}
+
+
+fn set_bit(settings: &Settings, x: f64, n: f64) -> f64 {
+    let width = bit_width(settings);
+
+    (to_bits(x, width) | bit_mask(n, width)) as f64
+}
+
+
+fn clear_bit(settings: &Settings, x: f64, n: f64) -> f64 {
+    let width = bit_width(settings);
+
+    (to_bits(x, width) & !bit_mask(n, width)) as f64
+}
+
+
+fn get_bit(settings: &Settings, x: f64, n: f64) -> f64 {
+    let width = bit_width(settings);
+
+    to_float(to_bits(x, width) & bit_mask(n, width) != 0)
+}
+
+
+// Integers beyond 2^53 can't all be represented exactly, so primality tests return NaN for them.
+// This also bounds the cost of trial division to around 2^26 / 3 iterations.
+pub(crate) const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+
+// Trial division using a 6k±1 wheel.
+fn is_prime_integer(n: u64) -> bool {
+    if n < 4 {
+        return n >= 2;
+    }
+
+    if n.is_multiple_of(2) || n.is_multiple_of(3) {
+        return false;
+    }
+
+    let mut i = 5;
+
+    while i * i <= n {
+        if n.is_multiple_of(i) || n.is_multiple_of(i + 2) {
+            return false;
+        }
+
+        i += 6;
+    }
+
+    true
+}
+
+
+// Splits an integer into its prime factors, as (prime, exponent) pairs in increasing order.
+pub fn prime_factors(mut n: u64) -> Vec<(u64, u32)> {
+    let mut factors = vec![];
+    let mut divisor = 2;
+
+    while divisor * divisor <= n {
+        let mut exponent = 0;
+
+        while n.is_multiple_of(divisor) {
+            n /= divisor;
+            exponent += 1;
+        }
+
+        if exponent > 0 {
+            factors.push((divisor, exponent));
+        }
+
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+
+    if n > 1 {
+        factors.push((n, 1));
+    }
+
+    factors
+}
+
+
+// Euclid's algorithm. gcd(0, n) is n, so zero is the identity when folding over a list.
+pub fn gcd(mut x: u64, mut y: u64) -> u64 {
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+
+    x
+}
+
+
+// Least common multiple, or None if it overflows. lcm(0, n) is 0.
+pub fn lcm(x: u64, y: u64) -> Option<u64> {
+    if x == 0 || y == 0 {
+        return Some(0);
+    }
+
+    (x / gcd(x, y)).checked_mul(y)
+}
+
+
+// Tests whether the integer part of a value is prime.
+fn is_prime(x: f64) -> f64 {
+    let x = x.trunc();
+
+    if x.is_nan() || x.abs() > MAX_EXACT_INTEGER {
+        return f64::NAN;
+    }
+
+    to_float(x >= 2.0 && is_prime_integer(x as u64))
+}
+
+
+// Finds the smallest prime greater than the integer part of a value.
+fn next_prime(x: f64) -> f64 {
+    let x = x.trunc();
+
+    if x.is_nan() || x >= MAX_EXACT_INTEGER {
+        return f64::NAN;
+    }
+
+    if x < 2.0 {
+        return 2.0;
+    }
+
+    let mut n = x as u64 + 1;
+
+    while !is_prime_integer(n) {
+        n += 1;
+    }
+
+    n as f64
+}
+
+
+// Computes base^exponent mod modulus by repeated squaring, using the integer parts of its arguments.
+// The result is between 0 and modulus - 1, even for negative bases. Negative exponents, a modulus less
+// than one, or values too large to be exact return NaN.
+fn mod_pow(base: f64, exponent: f64, modulus: f64) -> f64 {
+    let (base, exponent, modulus) = (base.trunc(), exponent.trunc(), modulus.trunc());
+
+    if [ base, exponent, modulus ].iter().any(|x| x.is_nan() || x.abs() > MAX_EXACT_INTEGER) || exponent < 0.0 || modulus < 1.0 {
+        return f64::NAN;
+    }
+
+    // Products of two values below 2^53 fit comfortably in 128 bits.
+    let modulus = modulus as u128;
+    let mut base = (base as i64).rem_euclid(modulus as i64) as u128;
+    let mut exponent = exponent as u64;
+    let mut result = 1 % modulus;
+
+    while exponent > 0 {
+        if exponent & 1 != 0 {
+            result = result * base % modulus;
+        }
+
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+
+    result as f64
+}
+
+
+// Finds x such that a * x mod m is 1, using the extended Euclidean algorithm on the integer parts of
+// its arguments. The result is between 0 and m - 1. Only values coprime to m have an inverse, so
+// NaN is returned if gcd(a, m) is not 1, as well as for a modulus less than one or inexact values.
+fn mod_inverse(a: f64, modulus: f64) -> f64 {
+    let (a, modulus) = (a.trunc(), modulus.trunc());
+
+    if [ a, modulus ].iter().any(|x| x.is_nan() || x.abs() > MAX_EXACT_INTEGER) || modulus < 1.0 {
+        return f64::NAN;
+    }
+
+    let modulus = modulus as i64;
+
+    // Invariant: old_s * a == old_r and s * a == r, modulo the modulus.
+    let (mut old_r, mut r) = ((a as i64).rem_euclid(modulus), modulus);
+    let (mut old_s, mut s) = (1i64, 0i64);
+
+    while r != 0 {
+        let quotient = old_r / r;
+
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+
+    if old_r != 1 {
+        return f64::NAN;
+    }
+
+    old_s.rem_euclid(modulus) as f64
+}
+
+
+// Computes the n'th Fibonacci number iteratively, so large n doesn't hit the recursion limit.
+// Results are exact up to fib(78). Beyond 2^53 they lose precision, and past fib(1476) overflow to infinity.
+fn fibonacci(n: f64) -> f64 {
+    let n = n.trunc();
+
+    if n.is_nan() || n < 0.0 {
+        return f64::NAN;
+    }
+
+    if n > 1476.0 {
+        return f64::INFINITY;
+    }
+
+    let (mut a, mut b) = (0.0, 1.0);
+
+    for _ in 0..n as u32 {
+        let next = a + b;
+        a = b;
+        b = next;
+    }
+
+    a
+}
+
+
+// Computes the binomial coefficient, ie. number of ways to choose k items from n.
+// Results lose precision beyond 2^53, and overflow to infinity rather than wrapping.
+fn choose(n: f64, k: f64) -> f64 {
+    let n = n.trunc();
+    let k = k.trunc();
+
+    if n.is_nan() || k.is_nan() || n < 0.0 {
+        return f64::NAN;
+    }
+
+    if k < 0.0 || k > n {
+        return 0.0;
+    }
+
+    // Multiplying and dividing alternately keeps each intermediate result an exact integer.
+    // C(n, k) >= 2^k, so this always overflows to infinity within a bounded number of steps.
+    let k = k.min(n - k);
+    let mut result: f64 = 1.0;
+    let mut i = 1.0;
+
+    while i <= k && result.is_finite() {
+        result = result * (n - k + i) / i;
+        i += 1.0;
+    }
+
+    result
+}
+
+
+// Lanczos approximation coefficients, for g = 7 and n = 9.
+const LANCZOS_G: f64 = 7.0;
+
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+
+// Evaluates the Lanczos series for x >= 0.5, returning the base term t and the sum.
+fn lanczos_sum(x: f64) -> (f64, f64) {
+    let x = x - 1.0;
+    let t = x + LANCZOS_G + 0.5;
+
+    let sum = LANCZOS_COEFFICIENTS.iter()
+                                  .enumerate()
+                                  .skip(1)
+                                  .fold(LANCZOS_COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64));
+
+    (t, sum)
+}
+
+
+fn is_gamma_pole(x: f64) -> bool {
+    x <= 0.0 && x == x.trunc()
+}
+
+
+// Gamma function, accurate to around 15 significant digits.
+// Poles at zero and negative integers return NaN.
+fn gamma(x: f64) -> f64 {
+    if is_gamma_pole(x) {
+        return f64::NAN;
+    }
+
+    if x < 0.5 {
+        // Reflection formula.
+        return f64::consts::PI / ((f64::consts::PI * x).sin() * gamma(1.0 - x));
+    }
+
+    let (t, sum) = lanczos_sum(x);
+
+    // Split the power in half to avoid overflowing before the exponential brings it back into range.
+    let half_power = t.powf((x - 0.5) / 2.0);
+
+    (2.0 * f64::consts::PI).sqrt() * half_power * (-t).exp() * half_power * sum
+}
+
+
+// Natural log of the absolute value of the gamma function, which stays finite long after gamma overflows.
+// Poles at zero and negative integers return infinity.
+fn lgamma(x: f64) -> f64 {
+    if is_gamma_pole(x) {
+        return f64::INFINITY;
+    }
+
+    if x < 0.5 {
+        // Reflection formula.
+        return (f64::consts::PI / (f64::consts::PI * x).sin().abs()).ln() - lgamma(1.0 - x);
+    }
+
+    let (t, sum) = lanczos_sum(x);
+
+    0.5 * (2.0 * f64::consts::PI).ln() + (x - 0.5) * t.ln() - t + sum.ln()
+}
+
+
+// Complementary error function, using a Chebyshev fit from Numerical Recipes.
+// Fractional error is less than 1.2e-7 everywhere, including far out in the tail.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+
+    let result = t * (-z * z - 1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96 + t * (0.096_784_18 +
+                      t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_203_98 + t * (1.488_515_87 +
+                      t * (-0.822_152_23 + t * 0.170_872_77))))))))).exp();
+
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+
+// Error function, accurate to within 1.2e-7. Exactly zero at zero, and symmetric so erf(-x) == -erf(x).
+fn erf(x: f64) -> f64 {
+    if x == 0.0 {
+        return x;
+    }
+
+    (1.0 - erfc(x.abs())).copysign(x)
+}
+
+
+// Steps x by one representable value toward y. Works by incrementing or decrementing the
+// raw bit pattern, which moves through subnormals, normals, and infinity in order.
+fn next_after(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() {
+        return f64::NAN;
+    }
+
+    if x == y {
+        return y;
+    }
+
+    if x == 0.0 {
+        // Smallest subnormal, with the sign of the direction we are heading.
+        return f64::from_bits(1).copysign(y);
+    }
+
+    let bits = x.to_bits();
+
+    // Increasing the bit pattern moves away from zero, regardless of sign.
+    if (y > x) == (x > 0.0) {
+        f64::from_bits(bits + 1)
+    } else {
+        f64::from_bits(bits - 1)
+    }
+}
+
+
+// Spacing between x and the next representable value of larger magnitude.
+fn ulp(x: f64) -> f64 {
+    let x = x.abs();
+
+    if x.is_infinite() {
+        f64::INFINITY
+    } else if x == f64::MAX {
+        // There is no larger finite value, so measure the gap below instead.
+        x - next_after(x, 0.0)
+    } else {
+        next_after(x, f64::INFINITY) - x
+    }
+}
+
+
+pub static OPERATORS: [Operator; 32] = operators![
+    "Special": [
+        // Markers that should never actually be evaluated.
+        { "(",   Precedence::Brace,      0, false },
+        { ")",   Precedence::Brace,      0, false },
+        { "=",   Precedence::Assign,     2, false },
+
+        // Component parts of the ternary ?: operator.
+        { "?",   Precedence::Ternary,    2, true },
+        { ":",   Precedence::Ternary,    2, true }
+    ],
+
+    "Boolean": [
+        { "->",  Precedence::Implies, right |x, y| to_float(!to_bool(x) || to_bool(y)) } with CONDITION_ARGUMENTS,
+        { "||",  Precedence::LogicalOr,  2, lazy |x| if to_bool(x) {0} else {1} } with CONDITION_ARGUMENTS,
+        { "^|",  Precedence::LogicalXor,    |x, y| to_float(to_bool(x) != to_bool(y)) } with CONDITION_ARGUMENTS,
+        { "&&",  Precedence::LogicalAnd, 2, lazy |x| if to_bool(x) {1} else {0} } with CONDITION_ARGUMENTS,
+        { "!",   Precedence::Unary,         |x| to_float(!to_bool(x)) } with CONDITION_ARGUMENTS
+    ],
+
+    "Bitwise": [
+        { "|",   Precedence::BinaryOr,      |x, y| (to_int(x)  |  to_int(y))        as f64 } with INTEGER_ARGUMENTS,
+        { "^^",  Precedence::BinaryXor,     |x, y| (to_int(x)  ^  to_int(y))        as f64 } with INTEGER_ARGUMENTS,
+        { "&",   Precedence::BinaryAnd,     |x, y| (to_int(x)  &  to_int(y))        as f64 } with INTEGER_ARGUMENTS,
+        { "<<",  Precedence::Shift,         |x, y| (to_int(x)  << (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+        { ">>",  Precedence::Shift,         |x, y| (to_uint(x) >> (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+        { ">>>", Precedence::Shift,         |x, y| (to_int(x)  >> (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+        { "~",   Precedence::Unary,         |x|    !to_int(x)                       as f64 } with INTEGER_ARGUMENTS
+    ],
+
+    "Comparison": [
+        { "==",  Precedence::CompareEq,     |x, y| to_float(x == y) },
+        { "!=",  Precedence::CompareEq,     |x, y| to_float(x != y) },
+        { "~=",  Precedence::CompareEq,     configurable |settings, x, y| to_float(approx_eq(x, y, settings.epsilon)) },
+        { "<",   Precedence::CompareDiff,   |x, y| to_float(x < y)  },
+        { ">",   Precedence::CompareDiff,   |x, y| to_float(x > y)  },
+        { "<=",  Precedence::CompareDiff,   |x, y| to_float(x <= y) },
+        { ">=",  Precedence::CompareDiff,   |x, y| to_float(x >= y) },
+        { "<?",  Precedence::MinMax,        |x, y| minimum(x, y)    },
+        { ">?",  Precedence::MinMax,        |x, y| maximum(x, y)    }
+    ],
+
+    "Arithmetic": [
+        { "+",   Precedence::Addition,      |x, y| x + y },
+        { "-",   Precedence::Addition,      |x, y| x - y },
+        { "*",   Precedence::Multiply,      |x, y| x * y },
+        { "/",   Precedence::Multiply,      |x, y| x / y },
+        { "%",   Precedence::Multiply,      |x, y| x.rem_euclid(y) },
+        { "^",   Precedence::Power,   right |x, y| power(x, y) }
+    ]
 ];
 
 
-pub static FUNCTIONS: [Operator; 31] = operators![
-    // Math functions.
-    { "max",   |x, y| if x > y {x} else {y} },
-    { "min",   |x, y| if x < y {x} else {y} },
-
-    { "sqrt",  |x| x.sqrt()  },
-    { "exp",   |x| x.exp()   },
-    { "ln",    |x| x.ln()    },
-    { "log",   |x| x.log10() },
-    { "log2",  |x| x.log2()  },
-    { "abs",   |x| x.abs()   },
-    { "ceil",  |x| x.ceil()  },
-    { "floor", |x| x.floor() },
-    { "round", |x| x.round() },
-
-    // Trig.
-    { "sin",   |x| x.sin()   },
-    { "cos",   |x| x.cos()   },
-    { "tan",   |x| x.tan()   },
-    { "sinh",  |x| x.sinh()  },
-    { "cosh",  |x| x.cosh()  },
-    { "tanh",  |x| x.tanh()  },
-    { "asin",  |x| x.asin()  },
-    { "acos",  |x| x.acos()  },
-    { "atan",  |x| x.atan()  },
-    { "asinh", |x| x.asinh() },
-    { "acosh", |x| x.acosh() },
-    { "atanh", |x| x.atanh() },
-
-    // Casts.
-    { "i8",    |x| x as i64 as i8  as f64 },
-    { "u8",    |x| x as i64 as u8  as f64 },
-    { "i16",   |x| x as i64 as i16 as f64 },
-    { "u16",   |x| x as i64 as u16 as f64 },
-    { "i32",   |x| x as i64 as i32 as f64 },
-    { "u32",   |x| x as i64 as u32 as f64 },
-
-    // Constants.
-    { "e",     || f64::consts::E  },
-    { "pi",    || f64::consts::PI }
+pub static FUNCTIONS: [Operator; 71] = operators![
+    "Math": [
+        { "max",   |x, y| maximum(x, y) },
+        { "min",   |x, y| minimum(x, y) },
+
+        // Truncated remainder keeps the sign of x, while Euclidean modulo matches the % operator.
+        { "rem",   |x, y| x % y },
+        { "mod",   |x, y| x.rem_euclid(y) },
+
+        { "sqrt",  |x| x.sqrt()  },
+        { "exp",   |x| x.exp()   },
+        { "ln",    |x| x.ln()    },
+        { "log",   |x| x.log10() },
+        { "log2",  |x| x.log2()  },
+        { "abs",   |x| x.abs()   },
+        { "ceil",  |x| x.ceil()  },
+        { "floor", |x| x.floor() },
+        { "round", |x| x.round() }
+    ],
+
+    "Interpolation": [
+        // Clamps to the range [0, 1]. NaN stays NaN.
+        { "saturate",   |x| x.clamp(0.0, 1.0) },
+        { "smoothstep", |edge0, edge1, x| smoothstep(edge0, edge1, x) },
+        { "wrap",       |x, lo, hi| wrap(x, lo, hi) }
+    ],
+
+    "Floating point": [
+        { "nextafter", |x, y| next_after(x, y) },
+        { "ulp",       |x| ulp(x) }
+    ],
+
+    "Special": [
+        { "gamma",  |x| gamma(x)  },
+        { "lgamma", |x| lgamma(x) },
+        { "erf",    |x| erf(x)    },
+        { "erfc",   |x| erfc(x)   }
+    ],
+
+    "Integer": [
+        { "isint",     |x| to_float(x.is_finite() && x == x.trunc()) },
+        { "isbool",    |x| to_float(x == 0.0 || x == 1.0) },
+        { "digitsum",  |x| digit_sum(x)  },
+        { "isprime",   |x| is_prime(x)   },
+        { "nextprime", |x| next_prime(x) },
+        { "fib",       |x| fibonacci(x)  },
+        { "choose",    |x, y| choose(x, y) },
+        { "modpow",    |x, y, z| mod_pow(x, y, z) },
+        { "modinv",    |x, y| mod_inverse(x, y) },
+
+        // Division truncated toward zero, with a remainder that has the sign of x. The % operator
+        // instead rounds down, so its remainder is never negative: -7 % 2 is 1, but imod(-7, 2) is -1.
+        { "idiv",      |x, y| truncated_divide(x, y) },
+        { "imod",      |x, y| x % y }
+    ],
+
+    "Trig": [
+        { "sin",   |x| x.sin()   } with ANGLE_ARGUMENTS,
+        { "cos",   |x| x.cos()   } with ANGLE_ARGUMENTS,
+        { "tan",   |x| x.tan()   } with ANGLE_ARGUMENTS,
+        { "sinh",  |x| x.sinh()  },
+        { "cosh",  |x| x.cosh()  },
+        { "tanh",  |x| x.tanh()  },
+        { "asin",  |x| x.asin()  } with ANGLE_RESULT,
+        { "acos",  |x| x.acos()  } with ANGLE_RESULT,
+        { "atan",  |x| x.atan()  } with ANGLE_RESULT,
+
+        // Angle of the point (x, y), in the range (-pi, pi].
+        { "atan2", |y, x| y.atan2(x) } with ANGLE_RESULT,
+
+        { "asinh", |x| x.asinh() },
+        { "acosh", |x| x.acosh() },
+        { "atanh", |x| x.atanh() }
+    ],
+
+    // Bitwise logic gates, operating on 32 bit integers.
+    "Bitwise": [
+        { "nand",  |x, y| !(to_int(x) & to_int(y)) as f64 } with INTEGER_ARGUMENTS,
+        { "nor",   |x, y| !(to_int(x) | to_int(y)) as f64 } with INTEGER_ARGUMENTS,
+        { "xnor",  |x, y| !(to_int(x) ^ to_int(y)) as f64 } with INTEGER_ARGUMENTS,
+
+        // Function forms of the <<, >> and >>> operators.
+        { "shl",   |x, y| (to_int(x)  << (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+        { "shr",   |x, y| (to_uint(x) >> (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+        { "sar",   |x, y| (to_int(x)  >> (to_int(y) & 31)) as f64 } with INTEGER_ARGUMENTS,
+
+        // Unlike the operators above, these work on integers of the size chosen by the width setting.
+        { "bitreverse", configurable |settings, x| bit_reverse(x, settings.width_for(x)) } with INTEGER_ARGUMENTS,
+        { "swapbytes",  configurable |settings, x| swap_bytes(x, settings.width_for(x))  } with INTEGER_ARGUMENTS,
+        { "setbit",     configurable |settings, x, n| set_bit(settings, x, n)   } with INTEGER_ARGUMENTS,
+        { "clearbit",   configurable |settings, x, n| clear_bit(settings, x, n) } with INTEGER_ARGUMENTS,
+        { "getbit",     configurable |settings, x, n| get_bit(settings, x, n)   } with INTEGER_ARGUMENTS
+    ],
+
+    "Casts": [
+        { "i8",    |x| x as i64 as i8  as f64 } with INTEGER_ARGUMENTS,
+        { "u8",    |x| x as i64 as u8  as f64 } with INTEGER_ARGUMENTS,
+        { "i16",   |x| x as i64 as i16 as f64 } with INTEGER_ARGUMENTS,
+        { "u16",   |x| x as i64 as u16 as f64 } with INTEGER_ARGUMENTS,
+        { "i32",   |x| x as i64 as i32 as f64 } with INTEGER_ARGUMENTS,
+        { "u32",   |x| x as i64 as u32 as f64 } with INTEGER_ARGUMENTS,
+
+        // Saturating casts clamp out of range values to the nearest limit, rather than wrapping.
+        // Converting directly from f64 saturates, and turns NaN into zero.
+        { "sat_i8",  |x| x as i8  as f64 } with INTEGER_ARGUMENTS,
+        { "sat_u8",  |x| x as u8  as f64 } with INTEGER_ARGUMENTS,
+        { "sat_i16", |x| x as i16 as f64 } with INTEGER_ARGUMENTS,
+        { "sat_u16", |x| x as u16 as f64 } with INTEGER_ARGUMENTS,
+        { "sat_i32", |x| x as i32 as f64 } with INTEGER_ARGUMENTS,
+        { "sat_u32", |x| x as u32 as f64 } with INTEGER_ARGUMENTS
+    ],
+
+    "Constants": [
+        { "e",     || f64::consts::E  },
+        { "pi",    || f64::consts::PI }
+    ]
 ];
 
 
 // Special operators, not accessible by name.
 pub static NEGATE:     Operator = operator!{ "-",       Precedence::Unary,         |x| -x };
+pub static PERCENT:    Operator = operator!{ "%",       Precedence::Unary,         |x| x / 100.0 };
 pub static TERNARY:    Operator = operator!{ "?:",      Precedence::Ternary,    3, lazy |x| if to_bool(x) {1} else {2} };
 pub static TERMINATOR: Operator = operator!{ "{arnie}", Precedence::Terminator, 0, false };
 
 
+// Special operators are created by the parser rather than looked up by name, so should not be shown to users.
+pub fn is_internal(op: OperatorRef) -> bool {
+    [ &NEGATE, &PERCENT, &TERNARY, &TERMINATOR ].iter().any(|internal| std::ptr::eq(*internal, op))
+}
+
+
+pub fn is_percent(op: OperatorRef) -> bool {
+    std::ptr::eq(op, &PERCENT)
+}
+
+
 pub fn find_operator(opname: &str) -> Option<OperatorRef> {
     OPERATORS.iter().find(|op| op == opname)
 }
@@ -229,3 +953,5 @@ pub fn find_operator(opname: &str) -> Option<OperatorRef> {
 pub fn find_function(opname: &str) -> Option<OperatorRef> {
     FUNCTIONS.iter().find(|op| op == opname)
 }
+
+
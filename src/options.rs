@@ -0,0 +1,281 @@
+use std::env;
+
+
+// Commandline flags, which are stripped out before the remaining arguments are evaluated.
+pub struct Options {
+    pub help: bool,
+    pub base: Option<u32>,
+    pub precision: Option<usize>,
+    pub color: ColorMode,
+    pub raw: bool,
+    pub interactive: bool,
+    pub echo: bool,
+    pub args: Vec<String>,
+}
+
+
+// Whether error messages are highlighted using ANSI color codes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+
+impl ColorMode {
+    // Auto mode only uses color when writing to a terminal, and the NO_COLOR environment variable is not set.
+    pub fn use_color(self, is_terminal: bool, no_color: bool) -> bool {
+        match self {
+            ColorMode::Auto   => is_terminal && !no_color,
+            ColorMode::Always => true,
+            ColorMode::Never  => false,
+        }
+    }
+}
+
+
+pub const USAGE: &str = "\
+Usage: erik [options] [expression...]
+       erik [options] <argument file>
+       erik [options] -
+
+With no arguments, expressions are read interactively from the console.
+Multiple arguments are joined together and evaluated as a single line.
+A single argument that names a file is evaluated one line at a time,
+skipping blank lines and comment lines that start with #.
+A single - argument reads lines from stdin, without showing any prompt.
+A .erikrc file in the current or home directory is evaluated at startup.
+Lines typed at the console are saved to .erik_history in the home directory.
+
+Options:
+    --base <n>    Number base to display output in, between 2 and 36.
+                  Can also be set with the ERIK_BASE environment variable.
+    --precision <n>
+                  Number of decimal places to display. By default, as many
+                  are shown as needed for the output to read back exactly.
+    --color <when>
+                  Highlight errors in red: auto, always, or never.
+                  Auto uses color for terminals unless NO_COLOR is set.
+    --raw         Print only results, with no prompt and no 0x prefix
+                  or digit grouping, for use in scripts.
+    --interactive Evaluate any arguments, then keep reading expressions
+                  from the console.
+    --echo        Show each line before its result, like 1+2 = 3.
+    --help        Show this message.";
+
+
+const BASE_USAGE: &str = "Usage: --base <number base between 2 and 36>";
+const PRECISION_USAGE: &str = "Usage: --precision <number of decimal places>";
+const COLOR_USAGE: &str = "Usage: --color <auto|always|never>";
+
+
+impl Options {
+    // Reads options from the process commandline and environment.
+    pub fn from_env() -> Options {
+        // Skip over the executable name.
+        Options::parse(env::args().skip(1).collect(), env::var("ERIK_BASE").ok())
+    }
+
+
+    pub fn parse(args: Vec<String>, base_variable: Option<String>) -> Options {
+        let mut options = Options {
+            help: false,
+            base: None,
+            precision: None,
+            color: ColorMode::Auto,
+            raw: false,
+            interactive: false,
+            echo: false,
+            args: vec![],
+        };
+
+        // The ERIK_BASE environment variable provides a default, which --base can override.
+        if let Some(base) = base_variable {
+            options.base = parse_base(Some(base));
+        }
+
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" => options.help = true,
+                "--base" => options.base = parse_base(args.next()),
+                "--precision" => options.precision = parse_precision(args.next()),
+                "--color" => options.color = parse_color(args.next()),
+                "--raw" => options.raw = true,
+                "--interactive" => options.interactive = true,
+                "--echo" => options.echo = true,
+                _ => options.args.push(arg),
+            }
+        }
+
+        options
+    }
+}
+
+
+// Invalid bases print the usage message, then fall back to the default.
+fn parse_base(arg: Option<String>) -> Option<u32> {
+    match arg.and_then(|arg| arg.parse().ok()) {
+        Some(base) if (2..=36).contains(&base) => Some(base),
+        _ => { println!("{}", BASE_USAGE); None }
+    }
+}
+
+
+// Invalid precisions print the usage message, then fall back to the default.
+fn parse_precision(arg: Option<String>) -> Option<usize> {
+    match arg.and_then(|arg| arg.parse().ok()) {
+        Some(precision) if precision <= 100 => Some(precision),
+        _ => { println!("{}", PRECISION_USAGE); None }
+    }
+}
+
+
+// Invalid color modes print the usage message, then fall back to auto.
+fn parse_color(arg: Option<String>) -> ColorMode {
+    match arg.as_deref() {
+        Some("auto")   => ColorMode::Auto,
+        Some("always") => ColorMode::Always,
+        Some("never")  => ColorMode::Never,
+        _ => { println!("{}", COLOR_USAGE); ColorMode::Auto }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+
+    #[test]
+    fn no_flags() {
+        let options = Options::parse(to_args(&[ "1+2", "3" ]), None);
+
+        assert!(!options.help);
+        assert_eq!(options.base, None);
+        assert_eq!(options.args, to_args(&[ "1+2", "3" ]));
+    }
+
+
+    #[test]
+    fn help_flag() {
+        assert!(Options::parse(to_args(&[ "--help" ]), None).help);
+        assert!(Options::parse(to_args(&[ "1+2", "--help" ]), None).help);
+
+        assert!(!Options::parse(to_args(&[ "help" ]), None).help);
+        assert!(!Options::parse(to_args(&[ "-help" ]), None).help);
+    }
+
+
+    #[test]
+    fn base_flag() {
+        let options = Options::parse(to_args(&[ "--base", "16", "255" ]), None);
+
+        assert_eq!(options.base, Some(16));
+        assert_eq!(options.args, to_args(&[ "255" ]));
+
+        let options = Options::parse(to_args(&[ "255", "--base", "2" ]), None);
+
+        assert_eq!(options.base, Some(2));
+        assert_eq!(options.args, to_args(&[ "255" ]));
+    }
+
+
+    #[test]
+    fn base_variable() {
+        let options = Options::parse(to_args(&[ "255" ]), Some(String::from("8")));
+        assert_eq!(options.base, Some(8));
+
+        let options = Options::parse(to_args(&[ "--base", "16", "255" ]), Some(String::from("8")));
+        assert_eq!(options.base, Some(16));
+    }
+
+
+    #[test]
+    fn invalid_base() {
+        assert_eq!(Options::parse(to_args(&[ "--base", "1" ]), None).base, None);
+        assert_eq!(Options::parse(to_args(&[ "--base", "37" ]), None).base, None);
+        assert_eq!(Options::parse(to_args(&[ "--base", "hex" ]), None).base, None);
+        assert_eq!(Options::parse(to_args(&[ "--base" ]), None).base, None);
+        assert_eq!(Options::parse(vec![], Some(String::from("0"))).base, None);
+    }
+
+
+    #[test]
+    fn precision_flag() {
+        assert_eq!(Options::parse(to_args(&[ "1/3" ]), None).precision, None);
+
+        let options = Options::parse(to_args(&[ "--precision", "4", "1/3" ]), None);
+
+        assert_eq!(options.precision, Some(4));
+        assert_eq!(options.args, to_args(&[ "1/3" ]));
+
+        assert_eq!(Options::parse(to_args(&[ "--precision", "101" ]), None).precision, None);
+        assert_eq!(Options::parse(to_args(&[ "--precision", "-1" ]), None).precision, None);
+        assert_eq!(Options::parse(to_args(&[ "--precision" ]), None).precision, None);
+    }
+
+
+    #[test]
+    fn raw_flag() {
+        assert!(!Options::parse(to_args(&[ "1+2" ]), None).raw);
+
+        let options = Options::parse(to_args(&[ "--raw", "1+2" ]), None);
+
+        assert!(options.raw);
+        assert_eq!(options.args, to_args(&[ "1+2" ]));
+    }
+
+
+    #[test]
+    fn interactive_flag() {
+        assert!(!Options::parse(to_args(&[ "x=5" ]), None).interactive);
+
+        let options = Options::parse(to_args(&[ "--interactive", "x=5" ]), None);
+
+        assert!(options.interactive);
+        assert_eq!(options.args, to_args(&[ "x=5" ]));
+    }
+
+
+    #[test]
+    fn echo_flag() {
+        assert!(!Options::parse(to_args(&[ "1+2" ]), None).echo);
+
+        let options = Options::parse(to_args(&[ "--echo", "1+2" ]), None);
+
+        assert!(options.echo);
+        assert_eq!(options.args, to_args(&[ "1+2" ]));
+    }
+
+
+    #[test]
+    fn color_flag() {
+        assert_eq!(Options::parse(to_args(&[ "1+2" ]), None).color, ColorMode::Auto);
+        assert_eq!(Options::parse(to_args(&[ "--color", "always", "1+2" ]), None).color, ColorMode::Always);
+        assert_eq!(Options::parse(to_args(&[ "--color", "never" ]), None).color, ColorMode::Never);
+        assert_eq!(Options::parse(to_args(&[ "--color", "never", "--color", "auto" ]), None).color, ColorMode::Auto);
+        assert_eq!(Options::parse(to_args(&[ "--color", "red" ]), None).color, ColorMode::Auto);
+        assert_eq!(Options::parse(to_args(&[ "--color" ]), None).color, ColorMode::Auto);
+
+        assert_eq!(Options::parse(to_args(&[ "--color", "always", "1+2" ]), None).args, to_args(&[ "1+2" ]));
+    }
+
+
+    #[test]
+    fn color_decision() {
+        assert!(ColorMode::Auto.use_color(true, false));
+        assert!(!ColorMode::Auto.use_color(false, false));
+        assert!(!ColorMode::Auto.use_color(true, true));
+
+        assert!(ColorMode::Always.use_color(false, true));
+        assert!(!ColorMode::Never.use_color(true, false));
+    }
+}
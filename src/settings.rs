@@ -0,0 +1,735 @@
+use std::iter::Peekable;
+
+use crate::tokens::{Token, Tokenizer};
+
+
+// User configurable settings, grouped together so embedders have one place to read them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+
+    // What number base(s) to display output in.
+    pub bases: Vec<u32>,
+
+    // Whether dividing by zero is an error, as opposed to returning infinity or NaN.
+    pub divide_by_zero_is_error: bool,
+
+    // How bitwise operators and casts convert fractional values to integers.
+    pub integer_conversion: IntegerConversion,
+
+    // Whether a value directly followed by a symbol or parenthesis is multiplied, so 2pi means 2*pi.
+    pub implicit_multiply: bool,
+
+    // How many decimal places to display, or None to show as many as needed.
+    pub precision: Option<usize>,
+
+    // How deeply user defined functions can recurse before evaluation is abandoned.
+    pub recursion_limit: u32,
+
+    // Whether questionable operations, such as using NaN as a condition, are errors.
+    pub strict: bool,
+
+    // Relative tolerance used by the ~= operator.
+    pub epsilon: f64,
+
+    // Text placed between values when output is displayed in more than one base.
+    pub base_separator: String,
+
+    // How many milliseconds evaluation can run for before it is abandoned, or None for no limit.
+    pub timeout: Option<u32>,
+
+    // Whether output is left undecorated for scripts, without 0x prefixes or binary digit grouping.
+    pub raw: bool,
+
+    // How many bits integers are wrapped to when displayed in bases other than 10.
+    pub integer_width: IntegerWidth,
+
+    // Range that decimal output is clamped to, or None to show values as they are.
+    // This only affects display, so ans and other uses of the value still see it unclamped.
+    pub output_range: Option<(f64, f64)>,
+
+    // Whether trig functions measure angles in radians or degrees.
+    pub angle_unit: AngleUnit,
+
+    // Whether each result is displayed after the line that produced it, like 1+2 = 3.
+    pub echo: bool,
+
+    // Whether integer arithmetic is done exactly using i64, rather than f64 which is only exact up to 2^53.
+    pub integer_mode: bool,
+}
+
+
+// Limits how wide output can get when displaying several number bases.
+pub const MAX_BASES: usize = 8;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegerConversion {
+    Truncate,
+    Round,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AngleUnit {
+    Radians,
+    Degrees,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegerWidth {
+    Fixed(u32),
+
+    // Uses the narrowest of 8, 16, 32 or 64 bits that holds each value, as two's complement if negative.
+    Auto,
+}
+
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            bases: vec![ 10 ],
+            divide_by_zero_is_error: false,
+            integer_conversion: IntegerConversion::Truncate,
+            implicit_multiply: false,
+            precision: None,
+            recursion_limit: 256,
+            strict: false,
+            epsilon: 1e-9,
+            base_separator: String::from("  "),
+            timeout: None,
+            raw: false,
+            integer_width: IntegerWidth::Fixed(32),
+            output_range: None,
+            angle_unit: AngleUnit::Radians,
+            echo: false,
+            integer_mode: false,
+        }
+    }
+
+
+    // Changes what number base(s) to display output in. Repeated bases are only shown once.
+    pub fn set_bases(&mut self, bases: &[u32]) -> Result<(), String> {
+        if bases.is_empty() || bases.iter().any(|base| !(2..=36).contains(base)) {
+            return Err(String::from("Number bases must be between 2 and 36."));
+        }
+
+        let mut unique_bases = vec![];
+
+        for base in bases {
+            if !unique_bases.contains(base) {
+                unique_bases.push(*base);
+            }
+        }
+
+        if unique_bases.len() > MAX_BASES {
+            return Err(format!("At most {} different number bases can be displayed.", MAX_BASES));
+        }
+
+        self.bases = unique_bases;
+
+        Ok(())
+    }
+
+
+    // Chooses how many bits to wrap an integer to. Auto width picks the narrowest that holds the value,
+    // so non-negative values must fit unsigned while negative ones must fit as two's complement.
+    pub fn width_for(&self, value: f64) -> u32 {
+        match self.integer_width {
+            IntegerWidth::Fixed(width) => width,
+
+            IntegerWidth::Auto => {
+                let value = value as i64;
+
+                [ 8, 16, 32 ].iter()
+                             .cloned()
+                             .find(|width| if value >= 0 { value < 1 << width } else { value >= -(1 << (width - 1)) })
+                             .unwrap_or(64)
+            }
+        }
+    }
+
+
+    // Changes a named setting, reading its new value from the tokenizer.
+    pub fn set(&mut self, name: &str, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+        match find_setting(name) {
+            Some(setting) => (setting.set)(self, tokenizer),
+            None => Err(format!("Unknown setting {}.", name)),
+        }
+    }
+
+
+    // Reads the value of a named setting, formatted as text.
+    pub fn get(&self, name: &str) -> Option<String> {
+        find_setting(name).map(|setting| (setting.get)(self))
+    }
+
+
+    // Lists the names and values of all settings.
+    pub fn list(&self) -> Vec<(&'static str, String)> {
+        SETTINGS.iter().map(|setting| (setting.name, (setting.get)(self))).collect()
+    }
+}
+
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings::new()
+    }
+}
+
+
+// Each named setting knows how to display and parse its value.
+struct Setting {
+    name: &'static str,
+    get:  fn(&Settings) -> String,
+    set:  fn(&mut Settings, &mut Peekable<Tokenizer>) -> Result<(), String>,
+}
+
+
+static SETTINGS: [Setting; 16] = [
+    Setting { name: "base",        get: get_bases,       set: set_bases       },
+    Setting { name: "divzero",     get: get_divzero,     set: set_divzero     },
+    Setting { name: "toint",       get: get_toint,       set: set_toint       },
+    Setting { name: "implicitmul", get: get_implicitmul, set: set_implicitmul },
+    Setting { name: "precision",   get: get_precision,   set: set_precision   },
+    Setting { name: "recursion",   get: get_recursion,   set: set_recursion   },
+    Setting { name: "strict",      get: get_strict,      set: set_strict      },
+    Setting { name: "epsilon",     get: get_epsilon,     set: set_epsilon     },
+    Setting { name: "basesep",     get: get_basesep,     set: set_basesep     },
+    Setting { name: "raw",         get: get_raw,         set: set_raw         },
+    Setting { name: "timeout",     get: get_timeout,     set: set_timeout     },
+    Setting { name: "width",       get: get_width,       set: set_width       },
+    Setting { name: "outrange",    get: get_outrange,    set: set_outrange    },
+    Setting { name: "angle",       get: get_angle,       set: set_angle       },
+    Setting { name: "echo",        get: get_echo,        set: set_echo        },
+    Setting { name: "intmode",     get: get_intmode,     set: set_intmode     },
+];
+
+
+fn find_setting(name: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.name == name)
+}
+
+
+fn get_bases(settings: &Settings) -> String {
+    settings.bases.iter()
+                  .map(|base| base.to_string())
+                  .collect::<Vec<String>>()
+                  .join(" ")
+}
+
+
+fn set_bases(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: base <list of up to 8 number bases between 2 and 36>";
+
+    let mut new_bases = vec![];
+
+    for token in tokenizer {
+        match token {
+            Ok(Token::Number(base)) if base.fract() == 0.0 => new_bases.push(base as u32),
+            _ => return Err(String::from(USAGE)),
+        }
+    }
+
+    settings.set_bases(&new_bases).map_err(|_| String::from(USAGE))
+}
+
+
+fn get_divzero(settings: &Settings) -> String {
+    format_bool(settings.divide_by_zero_is_error)
+}
+
+
+fn set_divzero(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.divide_by_zero_is_error = parse_bool("divzero", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn get_toint(settings: &Settings) -> String {
+    String::from(match settings.integer_conversion {
+        IntegerConversion::Truncate => "truncate",
+        IntegerConversion::Round    => "round",
+    })
+}
+
+
+fn set_toint(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: set toint <truncate|round>";
+
+    let conversion = match tokenizer.next() {
+        Some(Ok(Token::Text("truncate"))) => IntegerConversion::Truncate,
+        Some(Ok(Token::Text("round")))    => IntegerConversion::Round,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.integer_conversion = conversion; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_implicitmul(settings: &Settings) -> String {
+    format_bool(settings.implicit_multiply)
+}
+
+
+fn set_implicitmul(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.implicit_multiply = parse_bool("implicitmul", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn get_precision(settings: &Settings) -> String {
+    match settings.precision {
+        Some(precision) => precision.to_string(),
+        None => String::from("auto"),
+    }
+}
+
+
+fn set_precision(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: set precision <number of decimal places|auto>";
+
+    let precision = match tokenizer.next() {
+        Some(Ok(Token::Text("auto"))) => None,
+        Some(Ok(Token::Number(precision))) if is_whole_number(precision, 100) => Some(precision as usize),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.precision = precision; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_recursion(settings: &Settings) -> String {
+    settings.recursion_limit.to_string()
+}
+
+
+fn set_recursion(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: set recursion <maximum depth>";
+
+    let limit = match tokenizer.next() {
+        Some(Ok(Token::Number(limit))) if is_whole_number(limit, u32::MAX) => limit as u32,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.recursion_limit = limit; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_strict(settings: &Settings) -> String {
+    format_bool(settings.strict)
+}
+
+
+fn set_strict(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.strict = parse_bool("strict", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn get_epsilon(settings: &Settings) -> String {
+    settings.epsilon.to_string()
+}
+
+
+fn set_epsilon(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: epsilon <non-negative tolerance>";
+
+    let epsilon = match tokenizer.next() {
+        Some(Ok(Token::Number(epsilon))) if epsilon.is_finite() => epsilon,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.epsilon = epsilon; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_basesep(settings: &Settings) -> String {
+    format!("{:?}", settings.base_separator)
+}
+
+
+fn set_basesep(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: basesep '<separator text>'";
+
+    match (tokenizer.next(), tokenizer.next()) {
+        (Some(Ok(Token::Str(separator))), None) => {
+            settings.base_separator = separator;
+            Ok(())
+        }
+
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_raw(settings: &Settings) -> String {
+    format_bool(settings.raw)
+}
+
+
+fn set_raw(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.raw = parse_bool("raw", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn get_timeout(settings: &Settings) -> String {
+    match settings.timeout {
+        Some(timeout) => timeout.to_string(),
+        None => String::from("off"),
+    }
+}
+
+
+fn set_timeout(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: timeout <milliseconds|off>";
+
+    let timeout = match tokenizer.next() {
+        Some(Ok(Token::Text("off"))) => None,
+        Some(Ok(Token::Number(timeout))) if is_whole_number(timeout, u32::MAX) => Some(timeout as u32),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.timeout = timeout; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_width(settings: &Settings) -> String {
+    match settings.integer_width {
+        IntegerWidth::Fixed(width) => width.to_string(),
+        IntegerWidth::Auto => String::from("auto"),
+    }
+}
+
+
+fn set_width(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: set width <8|16|32|64|auto>";
+
+    let width = match tokenizer.next() {
+        Some(Ok(Token::Text("auto"))) => IntegerWidth::Auto,
+        Some(Ok(Token::Number(width))) if [ 8.0, 16.0, 32.0, 64.0 ].contains(&width) => IntegerWidth::Fixed(width as u32),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.integer_width = width; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_outrange(settings: &Settings) -> String {
+    match settings.output_range {
+        Some((min, max)) => format!("{} {}", min, max),
+        None => String::from("off"),
+    }
+}
+
+
+fn set_outrange(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: outrange <min max|off>";
+
+    let range = if let Some(Ok(Token::Text("off"))) = tokenizer.peek() {
+        tokenizer.next();
+        None
+    } else {
+        match (parse_signed_number(tokenizer), parse_signed_number(tokenizer)) {
+            (Some(min), Some(max)) if min <= max => Some((min, max)),
+            _ => return Err(String::from(USAGE)),
+        }
+    };
+
+    match tokenizer.next() {
+        None => { settings.output_range = range; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+fn get_angle(settings: &Settings) -> String {
+    String::from(match settings.angle_unit {
+        AngleUnit::Radians => "radians",
+        AngleUnit::Degrees => "degrees",
+    })
+}
+
+
+fn set_angle(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    const USAGE: &str = "Usage: set angle <radians|degrees>";
+
+    let angle_unit = match tokenizer.next() {
+        Some(Ok(Token::Text("radians"))) => AngleUnit::Radians,
+        Some(Ok(Token::Text("degrees"))) => AngleUnit::Degrees,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    match tokenizer.next() {
+        None => { settings.angle_unit = angle_unit; Ok(()) }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+
+// Reads a number that may be preceded by a minus sign, which the tokenizer returns as a separate operator.
+fn parse_signed_number(tokenizer: &mut Peekable<Tokenizer>) -> Option<f64> {
+    let is_negative = matches!(tokenizer.peek(), Some(Ok(Token::Operator(op))) if op == "-");
+
+    if is_negative {
+        tokenizer.next();
+    }
+
+    match tokenizer.next() {
+        Some(Ok(Token::Number(value))) => Some(if is_negative { -value } else { value }),
+        _ => None,
+    }
+}
+
+
+fn is_whole_number(value: f64, max: u32) -> bool {
+    (0.0..=max as f64).contains(&value) && value.fract() == 0.0
+}
+
+
+fn get_echo(settings: &Settings) -> String {
+    format_bool(settings.echo)
+}
+
+
+fn set_echo(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.echo = parse_bool("echo", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn get_intmode(settings: &Settings) -> String {
+    format_bool(settings.integer_mode)
+}
+
+
+fn set_intmode(settings: &mut Settings, tokenizer: &mut Peekable<Tokenizer>) -> Result<(), String> {
+    settings.integer_mode = parse_bool("intmode", tokenizer)?;
+
+    Ok(())
+}
+
+
+fn format_bool(value: bool) -> String {
+    String::from(if value { "on" } else { "off" })
+}
+
+
+fn parse_bool(name: &str, tokenizer: &mut Peekable<Tokenizer>) -> Result<bool, String> {
+    let value = match tokenizer.next() {
+        Some(Ok(Token::Text("on")))  => true,
+        Some(Ok(Token::Text("off"))) => false,
+        _ => return Err(format!("Usage: set {} <on|off>", name)),
+    };
+
+    match tokenizer.next() {
+        None => Ok(value),
+        Some(_) => Err(format!("Usage: set {} <on|off>", name)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    fn set(settings: &mut Settings, name: &str, value: &str) -> Result<(), String> {
+        settings.set(name, &mut Tokenizer::new(value).peekable())
+    }
+
+
+    #[test]
+    fn defaults() {
+        let settings = Settings::new();
+
+        assert_eq!(settings.list(), vec![
+            ("base",        String::from("10")),
+            ("divzero",     String::from("off")),
+            ("toint",       String::from("truncate")),
+            ("implicitmul", String::from("off")),
+            ("precision",   String::from("auto")),
+            ("recursion",   String::from("256")),
+            ("strict",      String::from("off")),
+            ("epsilon",     String::from("0.000000001")),
+            ("basesep",     String::from("\"  \"")),
+            ("raw",         String::from("off")),
+            ("timeout",     String::from("off")),
+            ("width",       String::from("32")),
+            ("outrange",    String::from("off")),
+            ("angle",       String::from("radians")),
+            ("echo",        String::from("off")),
+            ("intmode",     String::from("off")),
+        ]);
+    }
+
+
+    #[test]
+    fn set_and_get() {
+        let mut settings = Settings::new();
+
+        set(&mut settings, "base", "2 16").unwrap();
+        assert_eq!(settings.bases, vec![ 2, 16 ]);
+        assert_eq!(settings.get("base").unwrap(), "2 16");
+
+        set(&mut settings, "base", "16 2 16 10 2 2 2 2 2 2 2 2").unwrap();
+        assert_eq!(settings.get("base").unwrap(), "16 2 10");
+
+        set(&mut settings, "base", "2 3 4 5 6 7 8 9 2").unwrap();
+        assert_eq!(settings.bases.len(), MAX_BASES);
+
+        set(&mut settings, "divzero", "on").unwrap();
+        assert!(settings.divide_by_zero_is_error);
+        assert_eq!(settings.get("divzero").unwrap(), "on");
+
+        set(&mut settings, "divzero", "off").unwrap();
+        assert!(!settings.divide_by_zero_is_error);
+
+        set(&mut settings, "toint", "round").unwrap();
+        assert_eq!(settings.integer_conversion, IntegerConversion::Round);
+        assert_eq!(settings.get("toint").unwrap(), "round");
+
+        set(&mut settings, "implicitmul", "on").unwrap();
+        assert!(settings.implicit_multiply);
+
+        set(&mut settings, "precision", "4").unwrap();
+        assert_eq!(settings.precision, Some(4));
+        assert_eq!(settings.get("precision").unwrap(), "4");
+
+        set(&mut settings, "precision", "auto").unwrap();
+        assert_eq!(settings.precision, None);
+
+        set(&mut settings, "recursion", "1000").unwrap();
+        assert_eq!(settings.recursion_limit, 1000);
+        assert_eq!(settings.get("recursion").unwrap(), "1000");
+
+        set(&mut settings, "strict", "on").unwrap();
+        assert!(settings.strict);
+
+        set(&mut settings, "epsilon", "1e-6").unwrap();
+        assert_eq!(settings.epsilon, 1e-6);
+        assert_eq!(settings.get("epsilon").unwrap(), "0.000001");
+
+        set(&mut settings, "basesep", "' | '").unwrap();
+        assert_eq!(settings.base_separator, " | ");
+        assert_eq!(settings.get("basesep").unwrap(), "\" | \"");
+
+        set(&mut settings, "raw", "on").unwrap();
+        assert!(settings.raw);
+
+        set(&mut settings, "timeout", "500").unwrap();
+        assert_eq!(settings.timeout, Some(500));
+        assert_eq!(settings.get("timeout").unwrap(), "500");
+
+        set(&mut settings, "timeout", "off").unwrap();
+        assert_eq!(settings.timeout, None);
+
+        set(&mut settings, "width", "64").unwrap();
+        assert_eq!(settings.integer_width, IntegerWidth::Fixed(64));
+        assert_eq!(settings.get("width").unwrap(), "64");
+
+        set(&mut settings, "width", "auto").unwrap();
+        assert_eq!(settings.integer_width, IntegerWidth::Auto);
+        assert_eq!(settings.get("width").unwrap(), "auto");
+
+        set(&mut settings, "outrange", "-1 1").unwrap();
+        assert_eq!(settings.output_range, Some((-1.0, 1.0)));
+        assert_eq!(settings.get("outrange").unwrap(), "-1 1");
+
+        set(&mut settings, "outrange", "off").unwrap();
+        assert_eq!(settings.output_range, None);
+
+        set(&mut settings, "angle", "degrees").unwrap();
+        assert_eq!(settings.angle_unit, AngleUnit::Degrees);
+        assert_eq!(settings.get("angle").unwrap(), "degrees");
+
+        set(&mut settings, "echo", "on").unwrap();
+        assert!(settings.echo);
+
+        set(&mut settings, "intmode", "on").unwrap();
+        assert!(settings.integer_mode);
+
+        assert!(settings.get("bogus").is_none());
+    }
+
+
+    #[test]
+    fn set_errors() {
+        let mut settings = Settings::new();
+
+        assert_eq!(set(&mut settings, "bogus", "1").unwrap_err(), "Unknown setting bogus.");
+        assert_eq!(set(&mut settings, "divzero", "maybe").unwrap_err(), "Usage: set divzero <on|off>");
+        assert_eq!(set(&mut settings, "divzero", "on off").unwrap_err(), "Usage: set divzero <on|off>");
+        assert_eq!(set(&mut settings, "toint", "floor").unwrap_err(), "Usage: set toint <truncate|round>");
+        assert_eq!(set(&mut settings, "precision", "1.5").unwrap_err(), "Usage: set precision <number of decimal places|auto>");
+        assert_eq!(set(&mut settings, "precision", "-1").unwrap_err(), "Usage: set precision <number of decimal places|auto>");
+        assert_eq!(set(&mut settings, "recursion", "lots").unwrap_err(), "Usage: set recursion <maximum depth>");
+        assert_eq!(set(&mut settings, "epsilon", "-1").unwrap_err(), "Usage: epsilon <non-negative tolerance>");
+        assert_eq!(set(&mut settings, "timeout", "-5").unwrap_err(), "Usage: timeout <milliseconds|off>");
+        assert_eq!(set(&mut settings, "timeout", "5 off").unwrap_err(), "Usage: timeout <milliseconds|off>");
+        assert_eq!(set(&mut settings, "basesep", "|").unwrap_err(), "Usage: basesep '<separator text>'");
+        assert_eq!(set(&mut settings, "basesep", "'a' 'b'").unwrap_err(), "Usage: basesep '<separator text>'");
+        assert_eq!(set(&mut settings, "width", "12").unwrap_err(), "Usage: set width <8|16|32|64|auto>");
+        assert_eq!(set(&mut settings, "width", "auto 8").unwrap_err(), "Usage: set width <8|16|32|64|auto>");
+        assert_eq!(set(&mut settings, "outrange", "1").unwrap_err(), "Usage: outrange <min max|off>");
+        assert_eq!(set(&mut settings, "outrange", "1 -1").unwrap_err(), "Usage: outrange <min max|off>");
+        assert_eq!(set(&mut settings, "outrange", "0 1 2").unwrap_err(), "Usage: outrange <min max|off>");
+        assert_eq!(set(&mut settings, "outrange", "off 1").unwrap_err(), "Usage: outrange <min max|off>");
+        assert_eq!(set(&mut settings, "angle", "gradians").unwrap_err(), "Usage: set angle <radians|degrees>");
+        assert_eq!(set(&mut settings, "base", "").unwrap_err(), "Usage: base <list of up to 8 number bases between 2 and 36>");
+        assert_eq!(set(&mut settings, "base", "37").unwrap_err(), "Usage: base <list of up to 8 number bases between 2 and 36>");
+        assert_eq!(set(&mut settings, "base", "36.9").unwrap_err(), "Usage: base <list of up to 8 number bases between 2 and 36>");
+        assert_eq!(set(&mut settings, "base", "10 2.5").unwrap_err(), "Usage: base <list of up to 8 number bases between 2 and 36>");
+        assert_eq!(set(&mut settings, "base", "2 3 4 5 6 7 8 9 10").unwrap_err(), "Usage: base <list of up to 8 number bases between 2 and 36>");
+
+        assert_eq!(settings, Settings::new());
+    }
+
+
+    #[test]
+    fn rejected_set_keeps_value() {
+        let mut settings = Settings::new();
+
+        set(&mut settings, "precision", "4").unwrap();
+        set(&mut settings, "toint", "round").unwrap();
+        set(&mut settings, "recursion", "100").unwrap();
+        set(&mut settings, "epsilon", "0.5").unwrap();
+
+        let before = settings.clone();
+
+        assert!(set(&mut settings, "precision", "5 junk").is_err());
+        assert!(set(&mut settings, "toint", "truncate junk").is_err());
+        assert!(set(&mut settings, "recursion", "1000 junk").is_err());
+        assert!(set(&mut settings, "epsilon", "0.25 junk").is_err());
+        assert!(set(&mut settings, "timeout", "5 junk").is_err());
+        assert!(set(&mut settings, "divzero", "on junk").is_err());
+
+        assert_eq!(settings, before);
+        assert_eq!(settings.get("precision").unwrap(), "4");
+    }
+}
@@ -1,17 +1,38 @@
+use std::fmt;
 use std::str;
 use crate::ops;
 
 
 // Before parsing, input strings are tokenized into a linear sequence of these enums.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Token<'a> {
     Number(f64),
+
+    // Decimal integers beyond 2^53, which f64 cannot hold exactly but integer mode can.
+    Integer(i64),
+
     Text(&'a str),
+    Str(String),
     Operator(ops::OperatorRef),
 }
 
 
+// Token formatter, used by the tokens debug command.
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value)  => write!(f, "Number {}", value),
+            Token::Integer(value) => write!(f, "Integer {}", value),
+            Token::Text(text)     => write!(f, "Text '{}'", text),
+            Token::Str(text)      => write!(f, "String {:?}", text),
+            Token::Operator(op)   => write!(f, "Operator {}", op.name),
+        }
+    }
+}
+
+
 // Tokenizer iterates over input characters, and is itself iterable as a series of tokens.
+#[derive(Clone)]
 pub struct Tokenizer<'a> {
     input_iterator: str::Chars<'a>,
     remainder: &'a str,
@@ -43,7 +64,7 @@ impl<'a> Iterator for Tokenizer<'a> {
 
                 // Quoted strings.
                 if char == '"' || char == '\'' {
-                    return Some(Ok(self.read_quoted()));
+                    return Some(self.read_quoted());
                 }
 
                 // Could this be an operator?
@@ -52,7 +73,7 @@ impl<'a> Iterator for Tokenizer<'a> {
                 }
 
                 // Unknown single character.
-                return Some(Ok(self.read_unknown_character()));
+                Some(Ok(self.read_unknown_character()))
             }
             
             // End of the input stream.
@@ -64,10 +85,10 @@ impl<'a> Iterator for Tokenizer<'a> {
 
 impl<'a> Tokenizer<'a> {
     // Wraps a tokenizer around the provided string reference.
-    pub fn new(input: &str) -> Tokenizer {
+    pub fn new(input: &'a str) -> Tokenizer<'a> {
         Tokenizer {
             input_iterator: input.chars(),
-            remainder: &input,
+            remainder: input,
             peeked: None,
         }
     }
@@ -127,8 +148,22 @@ impl<'a> Tokenizer<'a> {
                     self.get();
                 }
 
-                // Also accept exponent markers, optionally followed by a minus sign.
-                Some(char) if char == 'e' => {
+                // Also accept exponent markers, optionally followed by a minus sign. An e that doesn't
+                // start an exponent is left for the next token, so 2e or 2exp(1) can be implicit multiplies.
+                // A doubled ee is still read as part of the number, so it is reported as malformed.
+                Some('e') => {
+                    let mut exponent = self.remainder['e'.len_utf8()..].chars();
+
+                    let is_exponent = match exponent.next() {
+                        Some(char) if char.is_ascii_digit() || char == 'e' => true,
+                        Some('-') => matches!(exponent.next(), Some(char) if char.is_ascii_digit()),
+                        _ => false,
+                    };
+
+                    if !is_exponent {
+                        break;
+                    }
+
                     self.get();
                     
                     if let Some('-') = self.peek() {
@@ -142,6 +177,19 @@ impl<'a> Tokenizer<'a> {
 
         let slice = &start_slice[..start_slice.len() - self.remainder.len()];
 
+        // An integer followed by # gives the number base of the digits that follow, as in 16#ff.
+        if self.peek() == Some('#') && slice.chars().all(|char| char.is_ascii_digit()) {
+            self.get();
+            return self.read_explicit_base(slice);
+        }
+
+        // Keep every digit of integers too large for f64, in case integer mode is enabled.
+        if let Ok(value) = slice.parse::<i64>() {
+            if value > ops::MAX_EXACT_INTEGER as i64 {
+                return Ok(Token::Integer(value));
+            }
+        }
+
         // The above logic will accept plenty of invalid strings, so this conversion can fail!
         match slice.parse() {
             Ok(value) => Ok(Token::Number(value)),
@@ -150,7 +198,33 @@ impl<'a> Tokenizer<'a> {
     }
 
 
-    // Reads an integer constant using binary or hexadecimal number base.
+    // Reads the digits of a base#digits constant, after the # character.
+    fn read_explicit_base(&mut self, base_slice: &str) -> Result<Token<'a>, String> {
+        let base = match base_slice.parse() {
+            Ok(base) if (2..=36).contains(&base) => base,
+            _ => return Err(format!("Number base {} must be between 2 and 36.", base_slice)),
+        };
+
+        if !matches!(self.peek(), Some(char) if char.is_digit(base)) {
+            return Err(format!("Base {} constant has no digits.", base));
+        }
+
+        let token = self.read_integer(base)?;
+
+        // Unlike 0x and 0b, digits that are not valid for the base are an error rather than the start of another token.
+        if let Some(char) = self.peek().filter(|char| char.is_alphanumeric()) {
+            while matches!(self.peek(), Some(char) if char.is_alphanumeric()) {
+                self.get();
+            }
+
+            return Err(format!("Invalid digit '{}' in base {} constant.", char, base));
+        }
+
+        Ok(token)
+    }
+
+
+    // Reads an integer constant in the specified number base.
     fn read_integer(&mut self, base: u32) -> Result<Token<'a>, String> {
         let mut value = 0u32;
 
@@ -158,17 +232,30 @@ impl<'a> Tokenizer<'a> {
             if let Some(digit) = char.to_digit(base) {
                 self.get();
 
-                match value.checked_mul(base) {
+                match value.checked_mul(base).and_then(|value| value.checked_add(digit)) {
                     Some(new_value) => value = new_value,
                     None => return Err(format!("Base {} constant overflowed 32 bit range.", base)),
                 }
-                
-                value |= digit;
             } else {
                 break;
             }
         }
 
+        if let Some('.') = self.peek() {
+            // Skip over the rest of the malformed constant.
+            while matches!(self.peek(), Some(char) if char.is_alphanumeric() || char == '.') {
+                self.get();
+            }
+
+            let base_name = match base {
+                2  => String::from("Binary"),
+                16 => String::from("Hex"),
+                _  => format!("Base {}", base),
+            };
+
+            return Err(format!("{} constants cannot have a fractional part.", base_name));
+        }
+
         Ok(Token::Number(value as f64))
     }
 
@@ -185,21 +272,37 @@ impl<'a> Tokenizer<'a> {
     }
 
 
-    // Reads a quoted string.
-    fn read_quoted(&mut self) -> Token<'a> {
+    // Reads a quoted string, processing backslash escapes.
+    fn read_quoted(&mut self) -> Result<Token<'a>, String> {
         let quote = self.get().unwrap();
-        let start_slice = self.remainder;
-        let mut end_slice = start_slice;
+        let mut result = String::new();
 
         loop {
             match self.get() {
                 Some(char) if char == quote => break,
-                Some(_) => end_slice = self.remainder,
-                None => break,
+                Some('\\') => result.push(self.read_escape()?),
+                Some(char) => result.push(char),
+                None => return Err(String::from("Unterminated string literal.")),
             }
         }
 
-        Token::Text(&start_slice[..start_slice.len() - end_slice.len()])
+        Ok(Token::Str(result))
+    }
+
+
+    // Reads the character following a backslash inside a quoted string.
+    fn read_escape(&mut self) -> Result<char, String> {
+        match self.get() {
+            Some('n')  => Ok('\n'),
+            Some('r')  => Ok('\r'),
+            Some('t')  => Ok('\t'),
+            Some('0')  => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('\'') => Ok('\''),
+            Some('"')  => Ok('"'),
+            Some(char) => Err(format!("Unknown escape sequence '\\{}'.", char)),
+            None => Err(String::from("Unterminated string literal.")),
+        }
     }
 
 
@@ -219,6 +322,9 @@ impl<'a> Tokenizer<'a> {
             ops::OPERATORS.iter().any(|op| op.name.starts_with(opname))
         }
 
+        // The input iterator has already moved past the peeked character, so this slice is the operator
+        // read so far plus that next character. Whitespace is never part of an operator name, so a
+        // space always ends the match, as in >> > which is two operators.
         while could_be_operator(&start_slice[..start_slice.len() - self.input_iterator.as_str().len()]) {
             self.get();
 
@@ -229,12 +335,13 @@ impl<'a> Tokenizer<'a> {
 
         let opname = &start_slice[..start_slice.len() - self.remainder.len()];
 
-        ops::find_operator(opname).map(|operator| Token::Operator(operator))
+        ops::find_operator(opname).map(Token::Operator)
     }
 }
 
 
 #[cfg(test)]
+#[allow(clippy::partialeq_to_none, clippy::assertions_on_constants, clippy::approx_constant)]
 mod tests {
     use super::*;
 
@@ -281,16 +388,35 @@ mod tests {
 
     #[test]
     fn quoted_strings() {
-        let mut t = Tokenizer::new("   ' a b '  \"what's up\"  'unclosed ");
+        let mut t = Tokenizer::new("   ' a b '  \"what's up\"  ''  'unclosed ");
         
-        assert!(matches!(t.next().unwrap(), Ok(Token::Text(" a b "))));
-        assert!(matches!(t.next().unwrap(), Ok(Token::Text("what's up"))));
-        assert!(matches!(t.next().unwrap(), Ok(Token::Text("unclosed "))));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == " a b "));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "what's up"));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s.is_empty()));
+        assert_eq!(t.next().unwrap().unwrap_err(), "Unterminated string literal.");
 
         assert!(t.next().is_none());
     }
 
 
+    #[test]
+    fn string_escapes() {
+        let mut t = Tokenizer::new(r#" "a\tb" "quote: \"" '\n' "\\" 'it\'s' "\r\0" "\q" "#);
+
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "a\tb"));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "quote: \""));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "\n"));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "\\"));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "it's"));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Str(s)) if s == "\r\0"));
+        assert_eq!(t.next().unwrap().unwrap_err(), "Unknown escape sequence '\\q'.");
+
+        assert_eq!(Tokenizer::new("'abc\\").next().unwrap().unwrap_err(), "Unterminated string literal.");
+        assert_eq!(Tokenizer::new("'abc\\'").next().unwrap().unwrap_err(), "Unterminated string literal.");
+        assert_eq!(Token::Str(String::from("a\"b")).to_string(), "String \"a\\\"b\"");
+    }
+
+
     fn expect_number(value: Option<Result<Token, String>>, expected: f64) {
         match value.unwrap().unwrap() {
             Token::Number(value) => assert_eq!(value, expected),
@@ -299,6 +425,19 @@ mod tests {
     }  
       
 
+    #[test]
+    fn large_integers() {
+        let mut t = Tokenizer::new("9007199254740992 9007199254740993 9223372036854775807 9223372036854775808 9007199254740993.0");
+
+        expect_number(t.next(), 9007199254740992.0);
+        assert!(matches!(t.next().unwrap(), Ok(Token::Integer(9007199254740993))));
+        assert!(matches!(t.next().unwrap(), Ok(Token::Integer(i64::MAX))));
+        expect_number(t.next(), 9223372036854775808.0);
+        expect_number(t.next(), 9007199254740992.0);
+        assert!(t.next().is_none());
+    }
+
+
     #[test]
     fn floats() {
         let mut t = Tokenizer::new("1 100 0.5 3.14 .6 007 10e4 10e-3 1.5e2 0.5x -10 3ee2 3..14");
@@ -366,6 +505,57 @@ mod tests {
     }
 
 
+    #[test]
+    fn explicit_base() {
+        let mut t = Tokenizer::new("16#ff 36#z 2#1010 36#ZZ 8#777 10#42 2#11111111111111111111111111111111 3#1.5 16#ff+1");
+
+        expect_number(t.next(), 255.0);
+        expect_number(t.next(), 35.0);
+        expect_number(t.next(), 10.0);
+        expect_number(t.next(), 1295.0);
+        expect_number(t.next(), 511.0);
+        expect_number(t.next(), 42.0);
+        expect_number(t.next(), 0xFFFFFFFFu32 as f64);
+
+        assert_eq!(t.next().unwrap().unwrap_err(), "Base 3 constants cannot have a fractional part.");
+
+        expect_number(t.next(), 255.0);
+        assert!(matches!(t.next().unwrap(), Ok(Token::Operator(_))));
+        expect_number(t.next(), 1.0);
+
+        assert!(t.next().is_none());
+
+        assert_eq!(Tokenizer::new("2#102").next().unwrap().unwrap_err(), "Invalid digit '2' in base 2 constant.");
+        assert_eq!(Tokenizer::new("16#fg").next().unwrap().unwrap_err(), "Invalid digit 'g' in base 16 constant.");
+        assert_eq!(Tokenizer::new("16#").next().unwrap().unwrap_err(), "Base 16 constant has no digits.");
+        assert_eq!(Tokenizer::new("8#9").next().unwrap().unwrap_err(), "Base 8 constant has no digits.");
+        assert_eq!(Tokenizer::new("37#1").next().unwrap().unwrap_err(), "Number base 37 must be between 2 and 36.");
+        assert_eq!(Tokenizer::new("1#1").next().unwrap().unwrap_err(), "Number base 1 must be between 2 and 36.");
+        assert_eq!(Tokenizer::new("36#zzzzzzz").next().unwrap().unwrap_err(), "Base 36 constant overflowed 32 bit range.");
+
+        let mut t = Tokenizer::new("1.5#1");
+
+        expect_number(t.next(), 1.5);
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("#"))));
+    }
+
+
+    #[test]
+    fn fractional_integers() {
+        let mut t = Tokenizer::new("0x1.5 0b1.0 0xff.ff.f 0x1+.5");
+
+        assert_eq!(t.next().unwrap().unwrap_err(), "Hex constants cannot have a fractional part.");
+        assert_eq!(t.next().unwrap().unwrap_err(), "Binary constants cannot have a fractional part.");
+        assert_eq!(t.next().unwrap().unwrap_err(), "Hex constants cannot have a fractional part.");
+
+        expect_number(t.next(), 1.0);
+        assert!(matches!(t.next().unwrap(), Ok(Token::Operator(_))));
+        expect_number(t.next(), 0.5);
+
+        assert!(t.next().is_none());
+    }
+
+
     #[test]
     fn operators() {
         let mut t = Tokenizer::new("x<y<=z!=");
@@ -385,5 +575,69 @@ mod tests {
         expect_operator(t.next(), "!=");
 
         assert!(t.next().is_none());
+
+        let mut t = Tokenizer::new("a<?b>?c<b?1:2");
+
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("a"))));
+        expect_operator(t.next(), "<?");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("b"))));
+        expect_operator(t.next(), ">?");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("c"))));
+        expect_operator(t.next(), "<");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("b"))));
+        expect_operator(t.next(), "?");
+
+        let mut t = Tokenizer::new("~x~=~y");
+
+        expect_operator(t.next(), "~");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("x"))));
+        expect_operator(t.next(), "~=");
+        expect_operator(t.next(), "~");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("y"))));
+
+        let mut t = Tokenizer::new("a->b>-c- >d");
+
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("a"))));
+        expect_operator(t.next(), "->");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("b"))));
+        expect_operator(t.next(), ">");
+        expect_operator(t.next(), "-");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("c"))));
+        expect_operator(t.next(), "-");
+        expect_operator(t.next(), ">");
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("d"))));
+
+        let mut t = Tokenizer::new("x< ?");
+
+        assert!(matches!(t.next().unwrap(), Ok(Token::Text("x"))));
+        expect_operator(t.next(), "<");
+        expect_operator(t.next(), "?");
+    }
+
+
+    #[test]
+    fn shift_operator_boundaries() {
+        fn operator_names(text: &str) -> Vec<String> {
+            Tokenizer::new(text).map(|token| match token.unwrap() {
+                Token::Operator(op) => String::from(op.name),
+                Token::Text(text) => String::from(text),
+                _ => panic!(),
+            }).collect()
+        }
+
+        assert_eq!(operator_names(">>>"), vec![ ">>>" ]);
+        assert_eq!(operator_names(">> >"), vec![ ">>", ">" ]);
+        assert_eq!(operator_names("> >>"), vec![ ">", ">>" ]);
+        assert_eq!(operator_names("> > >"), vec![ ">", ">", ">" ]);
+        assert_eq!(operator_names(">>>>"), vec![ ">>>", ">" ]);
+        assert_eq!(operator_names(">>>>>>"), vec![ ">>>", ">>>" ]);
+        assert_eq!(operator_names(">>="), vec![ ">>", "=" ]);
+        assert_eq!(operator_names(">>?"), vec![ ">>", "?" ]);
+        assert_eq!(operator_names(">>\t>"), vec![ ">>", ">" ]);
+        assert_eq!(operator_names("a >> >b"), vec![ "a", ">>", ">", "b" ]);
+        assert_eq!(operator_names("a>>>b"), vec![ "a", ">>>", "b" ]);
+        assert_eq!(operator_names("a>> b"), vec![ "a", ">>", "b" ]);
+        assert_eq!(operator_names("a> >>b"), vec![ "a", ">", ">>", "b" ]);
+        assert_eq!(operator_names("<<<"), vec![ "<<", "<" ]);
     }
 }
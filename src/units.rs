@@ -0,0 +1,123 @@
+// A unit converts to the base unit of its dimension by adding the offset then multiplying by the scale.
+// Only temperatures need an offset, since their zero points differ.
+struct Unit {
+    name:      &'static str,
+    dimension: &'static str,
+    scale:     f64,
+    offset:    f64,
+}
+
+
+macro_rules! units {
+    ($($dimension:literal: [ $(($name:literal, $scale:expr $(, $offset:expr)?)),* ]),*) => {
+        [ $($(Unit { name: $name, dimension: $dimension, scale: $scale, offset: 0.0 $(+ $offset)? }),*),* ]
+    };
+}
+
+
+// Base units are meters, kilograms and degrees Celsius.
+static UNITS: [Unit; 18] = units![
+    "length": [
+        ("mm",  0.001),
+        ("cm",  0.01),
+        ("m",   1.0),
+        ("km",  1000.0),
+        ("in",  0.0254),
+        ("ft",  0.3048),
+        ("yd",  0.9144),
+        ("mi",  1609.344),
+        ("nmi", 1852.0)
+    ],
+
+    "mass": [
+        ("mg",  0.000001),
+        ("g",   0.001),
+        ("kg",  1.0),
+        ("t",   1000.0),
+        ("oz",  0.028349523125),
+        ("lb",  0.45359237)
+    ],
+
+    "temperature": [
+        ("C",   1.0),
+        ("K",   1.0,       -273.15),
+        ("F",   5.0 / 9.0, -32.0)
+    ]
+];
+
+
+fn find_unit(name: &str) -> Result<&'static Unit, String> {
+    UNITS.iter()
+         .find(|unit| unit.name == name)
+         .ok_or_else(|| format!("Unknown unit {}.", name))
+}
+
+
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let from = find_unit(from)?;
+    let to = find_unit(to)?;
+
+    if from.dimension != to.dimension {
+        return Err(format!("Can't convert {} ({}) to {} ({}).", from.name, from.dimension, to.name, to.dimension));
+    }
+
+    if from.name == to.name {
+        return Ok(value);
+    }
+
+    let result = (value + from.offset) * from.scale / to.scale - to.offset;
+
+    // Snap results that are within a few ULPs of a 12 significant digit value, so converting 100 C to F
+    // gives 212 rather than 211.99999999999994, while keeping any real precision beyond 12 digits.
+    let rounded: f64 = format!("{:.11e}", result).parse().unwrap();
+
+    if (result - rounded).abs() <= rounded.abs() * f64::EPSILON * 4.0 {
+        Ok(rounded)
+    } else {
+        Ok(result)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn length() {
+        assert_eq!(convert(100.0, "km", "mi"), Ok(62.13711922373339));
+        assert_eq!(convert(1.0, "mi", "ft"), Ok(5280.0));
+        assert_eq!(convert(12.0, "in", "ft"), Ok(1.0));
+        assert_eq!(convert(3.0, "m", "m"), Ok(3.0));
+
+        // Results with more than 12 significant digits keep them.
+        assert_eq!(convert(123456789012345.0, "mm", "m"), Ok(123456789012.345));
+    }
+
+
+    #[test]
+    fn mass() {
+        assert_eq!(convert(1.0, "lb", "oz"), Ok(16.0));
+        assert_eq!(convert(1.0, "kg", "lb"), Ok(2.2046226218487757));
+    }
+
+
+    #[test]
+    fn temperature() {
+        assert_eq!(convert(100.0, "C", "F"), Ok(212.0));
+        assert_eq!(convert(32.0, "F", "C"), Ok(0.0));
+        assert_eq!(convert(-40.0, "C", "F"), Ok(-40.0));
+        assert_eq!(convert(0.0, "K", "C"), Ok(-273.15));
+        assert_eq!(convert(0.0, "F", "K"), Ok(255.3722222222222));
+    }
+
+
+    #[test]
+    fn errors() {
+        assert_eq!(convert(1.0, "km", "lb").unwrap_err(), "Can't convert km (length) to lb (mass).");
+        assert_eq!(convert(1.0, "C", "m").unwrap_err(), "Can't convert C (temperature) to m (length).");
+        assert_eq!(convert(1.0, "parsec", "m").unwrap_err(), "Unknown unit parsec.");
+        assert_eq!(convert(1.0, "m", "furlong").unwrap_err(), "Unknown unit furlong.");
+    }
+}